@@ -1,51 +1,394 @@
-use clap::Parser;
-use std::str::FromStr;
+use crate::{
+    config::absolute_path,
+    doctor::FixMode,
+    list::{self, ItemPattern, ListFilter, MatchOn, Pattern, SortKey},
+    output::Format,
+    trash::{Conflict, DuplicateChoice, RestoreOptions},
+    trash_item::ItemType,
+    trash_names::Naming,
+};
+use chrono::{DateTime, Duration, Local};
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
-#[derive(Debug)]
-pub enum Method {
-    Put,
-    Restore,
-    Empty,
-    Info,
+#[derive(Parser, Debug)]
+#[clap(version, about, long_about = None, author)]
+pub struct Args {
+    #[clap(subcommand)]
+    pub command: Command,
+
+    /// Explain all steps
+    #[clap(short, long, global = true)]
+    pub verbose: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Move files or directories to the trash
+    #[clap(visible_alias = "p")]
+    Put(PutArgs),
+
+    /// Restore trashed files to their original location
+    #[clap(visible_alias = "r")]
+    Restore(RestoreArgs),
+
+    /// Show trashed items in a table
+    List(ListArgs),
+
+    /// List what is inside a trashed directory
+    Ls(LsArgs),
+
+    /// Show the trash info of trashed items
+    #[clap(visible_alias = "i")]
+    Info(InfoArgs),
+
+    /// Permanently delete trashed items
+    #[clap(visible_alias = "rm")]
+    Purge(PurgeArgs),
+
+    /// Permanently delete everything in every trash can
+    #[clap(visible_alias = "e")]
+    Empty(EmptyArgs),
+
+    /// Report broken trash entries and optionally repair them
+    Doctor(DoctorArgs),
+
+    /// Reverse the last put or restore, or an earlier one by its id
+    Undo(UndoArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct PutArgs {
+    /// The target files or directories
+    #[clap(required = true)]
+    pub files: Vec<String>,
+
+    /// Trash file naming: `uuid` (foo.txt.<uuid>) or `counter` (foo.txt, foo.2.txt)
+    #[clap(long, default_value = "uuid", value_name = "NAMING")]
+    pub naming: Naming,
+
+    /// Print results as `text`, or as `json` or `ndjson` for scripts. See the readme for the schema
+    #[clap(long, default_value = "text", value_name = "FORMAT")]
+    pub format: Format,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct RestoreArgs {
+    /// Original paths, or paths in the trash's files or info directory. Pick from the trash when empty
+    #[clap(conflicts_with_all = &["glob", "regex"])]
+    pub files: Vec<String>,
+
+    #[clap(flatten)]
+    pub pattern: PatternArgs,
+
+    /// Restore every item trashed from this directory or below
+    #[clap(long, value_name = "DIR", conflicts_with = "files")]
+    pub under: Option<String>,
+
+    /// Restore every item trashed from the current directory or below
+    #[clap(long, conflicts_with_all = &["files", "under"])]
+    pub here: bool,
+
+    /// Overwrite existing files. Same as --on-conflict overwrite
+    #[clap(short, long, conflicts_with = "on-conflict")]
+    pub force: bool,
+
+    /// When the destination exists: `fail` `overwrite` `rename` `skip` `backup` (trash it first) or `ask`
+    #[clap(long, default_value = "fail", value_name = "MODE")]
+    pub on_conflict: Conflict,
+
+    /// Restore the most recently trashed item when several share a path
+    #[clap(long, conflicts_with_all = &["oldest", "all"])]
+    pub latest: bool,
+
+    /// Restore the first trashed item when several share a path
+    #[clap(long, conflicts_with = "all")]
+    pub oldest: bool,
+
+    /// Restore every item when several share a path
+    #[clap(long)]
+    pub all: bool,
+
+    /// Restore into this directory, or to this path, instead of the original location
+    #[clap(long, value_name = "PATH")]
+    pub to: Option<String>,
+
+    /// Print results as `text`, or as `json` or `ndjson` for scripts. See the readme for the schema
+    #[clap(long, default_value = "text", value_name = "FORMAT")]
+    pub format: Format,
 }
 
-impl FromStr for Method {
-    type Err = &'static str;
+impl RestoreArgs {
+    /// `None` unless items are chosen with --under, --here, --glob or --regex
+    pub fn filter(&self) -> Option<ListFilter> {
+        let filter = ListFilter {
+            path_prefix: scope(self.under.as_deref(), self.here),
+            pattern: self.pattern.pattern(),
+            ..ListFilter::default()
+        };
+        (filter.path_prefix.is_some() || filter.pattern.is_some()).then_some(filter)
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        use Method::*;
+    pub fn options(&self) -> RestoreOptions {
+        let duplicate_choice = match (self.latest, self.oldest, self.all) {
+            (true, _, _) => DuplicateChoice::Latest,
+            (_, true, _) => DuplicateChoice::Oldest,
+            (_, _, true) => DuplicateChoice::All,
+            _ => DuplicateChoice::Ask,
+        };
 
-        match s {
-            "p" | "put" | "Put" => Ok(Put),
-            "r" | "restore" | "Restore" => Ok(Restore),
-            "e" | "empty" | "Empty" => Ok(Empty),
-            "i" | "info" => Ok(Info),
-            _ => Err("Valid methods are `empty` 'info' `put` `restore`"),
+        RestoreOptions {
+            duplicate_choice,
+            conflict: match self.force {
+                true => Conflict::Overwrite,
+                false => self.on_conflict,
+            },
+            destination: self.to.as_deref().map(absolute_path),
         }
     }
 }
 
-// todo the usage details make method + option unclear.
-// It's probably better to make separate commands rather than overloading the method with a sometimes required argument
+#[derive(clap::Args, Debug)]
+pub struct ListArgs {
+    /// Sort by `date` (newest first), `size` (largest first) or `path`
+    #[clap(long, default_value = "date", value_name = "KEY")]
+    pub sort: SortKey,
 
-#[derive(Parser, Debug)]
-#[clap(version, about, long_about = None, author)]
-pub struct Args {
-    /// `e` | `empty`   `i` | 'info'   `p` | `put`   `r` | `restore`
-    pub method: Method,
+    /// Reverse the sort order
+    #[clap(short, long)]
+    pub reverse: bool,
 
-    /// The target files or directories
+    /// Only items trashed from this directory or below
+    #[clap(long, visible_alias = "under", value_name = "PREFIX")]
+    pub path: Option<String>,
+
+    /// Only items trashed from the current directory or below
+    #[clap(long, conflicts_with = "path")]
+    pub here: bool,
+
+    #[clap(flatten)]
+    pub pattern: PatternArgs,
+
+    /// Only items of a type: `file` `dir` or `link`
+    #[clap(long = "type", value_name = "TYPE")]
+    pub item_type: Option<ItemType>,
+
+    /// Only items trashed on or after a date: YYYY-MM-DD or YYYY-MM-DDThh:mm:ss
+    #[clap(long, value_name = "DATE", parse(try_from_str = list::parse_since))]
+    pub since: Option<DateTime<Local>>,
+
+    /// Only items trashed on or before a date: YYYY-MM-DD or YYYY-MM-DDThh:mm:ss
+    #[clap(long, value_name = "DATE", parse(try_from_str = list::parse_until))]
+    pub until: Option<DateTime<Local>>,
+
+    /// Show the table in $PAGER (less by default)
+    #[clap(long, conflicts_with = "format")]
+    pub pager: bool,
+
+    /// Print results as `text`, or as `json` or `ndjson` for scripts. See the readme for the schema
+    #[clap(long, default_value = "text", value_name = "FORMAT")]
+    pub format: Format,
+}
+
+impl ListArgs {
+    pub fn filter(&self) -> ListFilter {
+        ListFilter {
+            path_prefix: scope(self.path.as_deref(), self.here),
+            pattern: self.pattern.pattern(),
+            item_type: self.item_type,
+            since: self.since,
+            until: self.until,
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+pub struct InfoArgs {
+    /// Trash file names. Shows every item when empty
+    #[clap(conflicts_with_all = &["glob", "regex"])]
     pub files: Vec<String>,
 
-    /// Explain all steps
-    #[clap(short, long)]
-    pub verbose: bool,
+    #[clap(flatten)]
+    pub pattern: PatternArgs,
+
+    /// All (wildcard `*` like matches)
+    #[clap(short, long, requires = "files")]
+    pub all: bool,
+
+    /// Also show everything inside trashed directories
+    #[clap(long, conflicts_with = "format")]
+    pub tree: bool,
+
+    /// Show every item trashed from this directory or below
+    #[clap(long, value_name = "DIR", conflicts_with = "files")]
+    pub under: Option<String>,
+
+    /// Show every item trashed from the current directory or below
+    #[clap(long, conflicts_with_all = &["files", "under"])]
+    pub here: bool,
+
+    /// Print results as `text`, or as `json` or `ndjson` for scripts. See the readme for the schema
+    #[clap(long, default_value = "text", value_name = "FORMAT")]
+    pub format: Format,
+}
+
+impl InfoArgs {
+    /// `None` unless items are chosen with --under, --here, --glob or --regex
+    pub fn filter(&self) -> Option<ListFilter> {
+        let filter = ListFilter {
+            path_prefix: scope(self.under.as_deref(), self.here),
+            pattern: self.pattern.pattern(),
+            ..ListFilter::default()
+        };
+        (filter.path_prefix.is_some() || filter.pattern.is_some()).then_some(filter)
+    }
+}
 
-    /// Force non-recoverable deletes/overwrites
+#[derive(clap::Args, Debug)]
+pub struct LsArgs {
+    /// A trash name, a path in the trash's files or info directory, or an original path
+    pub item: String,
+
+    /// A directory or file inside the trashed directory
+    pub subpath: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct PurgeArgs {
+    /// Trash names, paths in the trash's files or info directory, or original paths
+    #[clap(required_unless_present_any = &["glob", "regex"])]
+    pub files: Vec<String>,
+
+    #[clap(flatten)]
+    pub pattern: PatternArgs,
+
+    /// Don't ask for confirmation
     #[clap(short, long)]
     pub force: bool,
+}
 
-    /// All (wildcard `*` like matches)
+impl PurgeArgs {
+    /// `None` unless items are chosen with --glob or --regex
+    pub fn filter(&self) -> Option<ListFilter> {
+        let filter = ListFilter {
+            pattern: self.pattern.pattern(),
+            ..ListFilter::default()
+        };
+        filter.pattern.is_some().then_some(filter)
+    }
+}
+
+#[derive(clap::Args, Debug)]
+pub struct EmptyArgs {
+    /// Don't ask for confirmation
     #[clap(short, long)]
-    pub all: bool,
+    pub force: bool,
+
+    /// Only delete items trashed longer ago than a duration like 30d, 12h or 1w2d
+    #[clap(long, value_name = "DURATION", parse(try_from_str = list::parse_duration))]
+    pub older_than: Option<Duration>,
+
+    /// Only delete items trashed within a duration like 30d, 12h or 1w2d
+    #[clap(long, value_name = "DURATION", parse(try_from_str = list::parse_duration))]
+    pub newer_than: Option<Duration>,
+
+    /// Delete the oldest items until the trash holds at most a size like 5G or 500MB
+    #[clap(long, value_name = "SIZE", parse(try_from_str = list::parse_size), conflicts_with_all = &["older-than", "newer-than"])]
+    pub keep_size: Option<u64>,
+
+    /// With --older-than, --newer-than or --keep-size, also delete items whose info file can't be read
+    #[clap(long)]
+    pub include_corrupt: bool,
+
+    /// Only show what would be deleted
+    #[clap(short = 'n', long)]
+    pub dry_run: bool,
+}
+
+impl EmptyArgs {
+    /// `None` when everything is to be deleted
+    pub fn filter(&self) -> Option<ListFilter> {
+        if self.older_than.is_none() && self.newer_than.is_none() {
+            return None;
+        }
+
+        let now = Local::now();
+        Some(ListFilter {
+            since: self.newer_than.map(|duration| now - duration),
+            until: self.older_than.map(|duration| now - duration),
+            ..ListFilter::default()
+        })
+    }
+}
+
+#[derive(clap::Args, Debug)]
+pub struct DoctorArgs {
+    /// Repair what is found: `regenerate` missing info files, `quarantine` or `delete` broken entries
+    #[clap(long, value_name = "MODE")]
+    pub fix: Option<FixMode>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct UndoArgs {
+    /// The id of an operation, as shown by --list. The most recent one when empty
+    pub id: Option<String>,
+
+    /// Show the journal of operations instead, newest first
+    #[clap(long, conflicts_with = "id")]
+    pub list: bool,
+
+    /// Naming of restored items that go back to the trash: `uuid` (foo.txt.<uuid>) or `counter` (foo.txt, foo.2.txt)
+    #[clap(long, default_value = "uuid", value_name = "NAMING")]
+    pub naming: Naming,
+}
+
+/// `--glob` or `--regex`, shared by the commands that pick items
+#[derive(clap::Args, Debug)]
+pub struct PatternArgs {
+    /// Items whose name or original path (see --match-on) matches a glob like `*.log`
+    #[clap(long, visible_alias = "name", value_name = "GLOB", conflicts_with = "regex")]
+    pub glob: Option<glob::Pattern>,
+
+    /// Items whose name or original path (see --match-on) contains a match of a regular expression
+    #[clap(long, value_name = "REGEX")]
+    pub regex: Option<regex::Regex>,
+
+    /// What --glob and --regex look at: the original `path`, or the `name` (original or trash name)
+    #[clap(long, default_value = "name", value_name = "TARGET")]
+    pub match_on: MatchOn,
+}
+
+impl PatternArgs {
+    pub fn pattern(&self) -> Option<ItemPattern> {
+        let pattern = match (&self.glob, &self.regex) {
+            (Some(glob), _) => Pattern::Glob(glob.clone()),
+            (_, Some(regex)) => Pattern::Regex(regex.clone()),
+            _ => return None,
+        };
+        Some(ItemPattern {
+            pattern,
+            match_on: self.match_on,
+        })
+    }
+}
+
+/// The directory given with `--under`, or pwd with `--here`
+fn scope(under: Option<&str>, here: bool) -> Option<PathBuf> {
+    match here {
+        true => Some(absolute_path(".")),
+        false => under.map(absolute_path),
+    }
+}
+
+#[test]
+fn parses_aliases() {
+    let parse = |args: &[&str]| Args::try_parse_from(args).map(|args| args.command);
+
+    assert!(matches!(parse(&["trash", "p", "foo.txt"]), Ok(Command::Put(put)) if put.files == ["foo.txt"]));
+    assert!(matches!(parse(&["trash", "r", "foo.txt"]), Ok(Command::Restore(restore)) if restore.files == ["foo.txt"]));
+    assert!(matches!(parse(&["trash", "e"]), Ok(Command::Empty(_))));
+    assert!(matches!(parse(&["trash", "i", "foo.txt"]), Ok(Command::Info(info)) if info.files == ["foo.txt"]));
+    // `Put` was one of the spellings of the old METHOD positional
+    assert!(parse(&["trash", "Put", "foo.txt"]).is_err());
+    assert!(parse(&["trash", "foo.txt"]).is_err());
 }
@@ -0,0 +1,299 @@
+use crate::{
+    output,
+    trash_item::{ItemType, TrashItem},
+};
+use humansize::{file_size_opts as options, FileSize};
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+/// A file in a trashed directory tree. Directory sizes include everything below them
+struct Entry {
+    name: OsString,
+    /// `None` when the entry couldn't be looked at
+    item_type: Option<ItemType>,
+    size: u64,
+    link_target: Option<PathBuf>,
+    children: Vec<Entry>,
+    /// Why the entry or its contents couldn't be read. The rest of the tree is still read
+    error: Option<String>,
+}
+
+impl Entry {
+    fn read(path: &Path) -> io::Result<Entry> {
+        let metadata = fs::symlink_metadata(path)?;
+        let mut error = None;
+        let (item_type, link_target) = match metadata.file_type() {
+            t if t.is_symlink() => match fs::read_link(path) {
+                Ok(target) => (ItemType::Link, Some(target)),
+                Err(e) => {
+                    error = Some(e.to_string());
+                    (ItemType::Link, None)
+                }
+            },
+            t if t.is_dir() => (ItemType::Dir, None),
+            _ => (ItemType::File, None),
+        };
+
+        let mut children = vec![];
+        if item_type == ItemType::Dir {
+            match fs::read_dir(path) {
+                Ok(entries) => {
+                    for entry in entries {
+                        children.push(match entry {
+                            Ok(entry) => {
+                                Entry::read(&entry.path()).unwrap_or_else(|e| Entry::unreadable(entry.file_name(), e))
+                            }
+                            Err(e) => Entry::unreadable(OsString::from("?"), e),
+                        });
+                    }
+                }
+                Err(e) => error = Some(e.to_string()),
+            }
+            children.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+
+        Ok(Entry {
+            name: path.file_name().unwrap_or(path.as_os_str()).to_owned(),
+            item_type: Some(item_type),
+            size: metadata.len() + children.iter().map(|child| child.size).sum::<u64>(),
+            link_target,
+            children,
+            error,
+        })
+    }
+
+    /// An entry that vanished or can't be looked at while its directory was read
+    fn unreadable(name: OsString, e: io::Error) -> Entry {
+        Entry {
+            name,
+            item_type: None,
+            size: 0,
+            link_target: None,
+            children: vec![],
+            error: Some(e.to_string()),
+        }
+    }
+
+    /// `name/` for directories and `name -> target` for symlinks
+    fn label(&self) -> String {
+        let name = self.name.to_string_lossy();
+        let label = match (&self.item_type, &self.link_target) {
+            (Some(ItemType::Dir), _) => format!("{}/", name),
+            (_, Some(target)) => format!("{} -> {}", name, target.display()),
+            _ => name.into_owned(),
+        };
+        match &self.error {
+            Some(e) => format!("{}  (unreadable: {})", label, e),
+            None => label,
+        }
+    }
+
+    fn human_size(&self) -> String {
+        self.size.file_size(options::CONVENTIONAL).unwrap()
+    }
+
+    fn row(&self) -> String {
+        let item_type = self
+            .item_type
+            .map_or("?".to_string(), |item_type| item_type.to_string());
+        format!("{:<4}  {:>10}  {}", item_type, self.human_size(), self.label())
+    }
+}
+
+/// The item a user named by trash name, Trash/files or Trash/info path, or original path.
+/// The latest is taken when several were trashed from the same path
+pub fn find_item(user_path: &str) -> TrashItem {
+    let items = TrashItem::all();
+    let mut matches: Vec<&TrashItem> = match TrashItem::named(&items, user_path) {
+        Ok(matches) => matches,
+        Err(e) => {
+            output::error(e);
+            output::exit(1)
+        }
+    };
+    // newest first
+    matches.sort_by_key(|item| std::cmp::Reverse(item.trash_info.deletion_date));
+
+    match matches.len() {
+        0 => {
+            output::info_stderr(format!("File not found in trash. {:?}", user_path));
+            output::exit(1)
+        }
+        1 => {}
+        n => output::info_stderr(format!(
+            "{} items were trashed from {}. Using the latest, name another by its trash name",
+            n,
+            matches[0].trash_info.source_path.display()
+        )),
+    }
+    let latest = matches[0].trash_paths.trash_info_path.clone();
+    items
+        .into_iter()
+        .find(|item| item.trash_paths.trash_info_path == latest)
+        .unwrap()
+}
+
+/// The path of `subpath` inside a trashed item. Bails on paths that would leave the item
+pub fn path_inside(item: &TrashItem, subpath: &Path) -> PathBuf {
+    let escapes = subpath
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+    if escapes {
+        output::error(format!(
+            "{} must be a relative path inside the trashed item",
+            subpath.display()
+        ));
+        output::exit(1)
+    }
+
+    match item.path_inside(subpath) {
+        Some(path) => path,
+        None => {
+            output::info_stderr(format!(
+                "{} is not in {}, or is behind a symlink",
+                subpath.display(),
+                item.trash_info.trash_file_name.display()
+            ));
+            output::exit(1)
+        }
+    }
+}
+
+/// Lists a trashed directory, or a directory inside it, with the types and sizes of its entries
+pub fn ls(user_path: &str, subpath: Option<&Path>) {
+    let item = find_item(user_path);
+    let path = match subpath {
+        Some(subpath) => path_inside(&item, subpath),
+        None => item.trash_paths.trash_file_path.clone(),
+    };
+
+    let entry = match Entry::read(&path) {
+        Ok(entry) => entry,
+        Err(e) => {
+            output::error(format!("Unable to read {}. {}", path.display(), e));
+            output::exit(1)
+        }
+    };
+
+    match entry.item_type {
+        Some(ItemType::Dir) => entry.children.iter().for_each(|child| println!("{}", child.row())),
+        _ => println!("{}", entry.row()),
+    }
+}
+
+/// Prints everything below a trashed directory as a tree
+pub fn print_tree(trash_file_path: &Path) {
+    match Entry::read(trash_file_path) {
+        Ok(entry) => print!("{}", tree(&entry)),
+        Err(e) => output::warning(format!("Unable to read {}. {}", trash_file_path.display(), e)),
+    }
+}
+
+/// The entry followed by everything below it, one line each
+fn tree(entry: &Entry) -> String {
+    let mut tree = format!("{}  {}\n", entry.label(), entry.human_size());
+    push_children(&mut tree, entry, "");
+    tree
+}
+
+fn push_children(tree: &mut String, entry: &Entry, prefix: &str) {
+    for (i, child) in entry.children.iter().enumerate() {
+        let is_last = i + 1 == entry.children.len();
+        let (branch, indent) = match is_last {
+            true => ("└── ", "    "),
+            false => ("├── ", "│   "),
+        };
+
+        tree.push_str(&format!(
+            "{}{}{}  {}\n",
+            prefix,
+            branch,
+            child.label(),
+            child.human_size()
+        ));
+        push_children(tree, child, &format!("{}{}", prefix, indent));
+    }
+}
+
+#[test]
+fn stays_inside_item() {
+    let (root, item) = crate::trash_item::trashed_dir_with_link();
+
+    assert_eq!(
+        Some(item.trash_paths.trash_file_path.join("sub/file")),
+        item.path_inside(Path::new("./sub/file"))
+    );
+    assert_eq!(None, item.path_inside(Path::new("..")));
+    assert_eq!(None, item.path_inside(Path::new("sub/../../d")));
+    assert_eq!(None, item.path_inside(&root.join("outside/victim")));
+    assert_eq!(None, item.path_inside(Path::new("link/victim")));
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn reads_tree() {
+    let (root, item) = crate::trash_item::trashed_dir_with_link();
+
+    let entry = Entry::read(&item.trash_paths.trash_file_path).unwrap();
+    let children: Vec<(String, Option<ItemType>)> = entry
+        .children
+        .iter()
+        .map(|child| (child.label(), child.item_type))
+        .collect();
+    assert_eq!(
+        vec![
+            (
+                format!("link -> {}", root.join("outside").display()),
+                Some(ItemType::Link)
+            ),
+            ("sub/".to_string(), Some(ItemType::Dir)),
+        ],
+        children
+    );
+    assert_eq!(8, entry.children[1].children[0].size);
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn draws_tree() {
+    let entry = |name: &str, item_type, size, children| Entry {
+        name: OsString::from(name),
+        item_type,
+        size,
+        link_target: None,
+        children,
+        error: None,
+    };
+    let unreadable = Entry::unreadable(
+        OsString::from("locked"),
+        io::Error::from(io::ErrorKind::PermissionDenied),
+    );
+    let nested = entry(
+        "nested",
+        Some(ItemType::Dir),
+        10,
+        vec![entry("b.txt", Some(ItemType::File), 10, vec![])],
+    );
+    let root = entry(
+        "d",
+        Some(ItemType::Dir),
+        13,
+        vec![entry("a.txt", Some(ItemType::File), 3, vec![]), unreadable, nested],
+    );
+
+    assert_eq!(
+        concat!(
+            "d/  13 B\n",
+            "├── a.txt  3 B\n",
+            "├── locked  (unreadable: permission denied)  0 B\n",
+            "└── nested/  10 B\n",
+            "    └── b.txt  10 B\n",
+        ),
+        tree(&root)
+    );
+}
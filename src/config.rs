@@ -1,6 +1,4 @@
-use crate::args::Method;
-use crate::GLOBAL;
-use colored::Colorize;
+use crate::{output, GLOBAL};
 use std::{
     env, fs,
     path::{Component, Path, PathBuf},
@@ -13,66 +11,69 @@ pub struct Config {
 }
 
 impl Config {
-    pub fn new(method: &Method, user_path: String) -> Self {
-        if GLOBAL.verbose() {
-            println!("{} Canonicalizing file paths.", "Info:".blue());
-        }
-
-        match method {
-            Method::Empty => unreachable!(),
-            Method::Info => unreachable!(),
-            Method::Put => {
-                let source_path = match fs::canonicalize(&user_path) {
-                    Ok(p) => p,
-                    Err(e) => {
-                        eprintln!("{} Unable to canonicalize file path: {}", "Err:".red(), e);
-                        eprintln!("{} Does this problem path exist? {}", "Hint:".yellow(), &user_path);
-                        std::process::exit(1)
-                    }
-                };
+    pub fn for_put(user_path: String) -> Self {
+        Self::verbose_canonicalizing();
 
-                let file_basename: PathBuf = match source_path.file_name() {
-                    Some(f) => f.into(),
-                    None => {
-                        // the canonicalization should make this unreachable
-                        eprintln!("{} Unable to parse file source path: {:?}", "Err:".red(), source_path);
-                        std::process::exit(1)
-                    }
-                };
-
-                Config {
-                    file_basename,
-                    source_path,
-                }
+        let source_path = match fs::canonicalize(&user_path) {
+            Ok(p) => p,
+            Err(e) => {
+                output::error(format!("Unable to canonicalize file path: {}", e));
+                output::hint(format!("Does this problem path exist? {}", &user_path));
+                output::exit(1)
             }
+        };
 
-            Method::Restore => {
-                let pwd = match env::current_dir() {
-                    Ok(d) => d,
-                    Err(e) => {
-                        eprintln!("{} Unable to process pwd: {}", "Err:".red(), e);
-                        std::process::exit(1)
-                    }
-                };
+        let file_basename: PathBuf = match source_path.file_name() {
+            Some(f) => f.into(),
+            None => {
+                // the canonicalization should make this unreachable
+                output::error(format!("Unable to parse file source path: {:?}", source_path));
+                output::exit(1)
+            }
+        };
 
-                let source_path: PathBuf = [pwd, PathBuf::from(user_path)].iter().collect();
-                let source_path = normalize_path(&source_path);
+        Config {
+            file_basename,
+            source_path,
+        }
+    }
 
-                let file_basename: PathBuf = match source_path.file_name() {
-                    Some(f) => f.into(),
-                    None => {
-                        eprintln!("{} Unable to parse file path: {:?}", "Err:".red(), source_path);
-                        std::process::exit(1)
-                    }
-                };
+    pub fn for_restore(user_path: String) -> Self {
+        Self::verbose_canonicalizing();
+        let source_path = absolute_path(&user_path);
 
-                Config {
-                    file_basename,
-                    source_path,
-                }
+        let file_basename: PathBuf = match source_path.file_name() {
+            Some(f) => f.into(),
+            None => {
+                output::error(format!("Unable to parse file path: {:?}", source_path));
+                output::exit(1)
             }
+        };
+
+        Config {
+            file_basename,
+            source_path,
         }
     }
+
+    fn verbose_canonicalizing() {
+        if GLOBAL.verbose() {
+            output::info("Canonicalizing file paths.");
+        }
+    }
+}
+
+/// Resolves a path that may not exist against pwd without following symlinks
+pub fn absolute_path(user_path: &str) -> PathBuf {
+    let pwd = match env::current_dir() {
+        Ok(d) => d,
+        Err(e) => {
+            output::error(format!("Unable to process pwd: {}", e));
+            output::exit(1)
+        }
+    };
+
+    normalize_path(&pwd.join(user_path))
 }
 
 pub fn normalize_path(path: &Path) -> PathBuf {
@@ -0,0 +1,241 @@
+use crate::{
+    file_ops, output,
+    trash_dir_paths::TrashDirPaths,
+    trash_info::{decode_path, encode_path},
+    trash_names::TrashNames,
+    GLOBAL,
+};
+use std::{
+    collections::HashMap,
+    io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+/// The spec's `Trash/directorysizes` cache of `<size> <info mtime> <percent-encoded dir name>` lines
+pub struct DirectorySizes {
+    path: PathBuf,
+    entries: Vec<DirectorySize>,
+}
+
+struct DirectorySize {
+    size: u64,
+    mtime: i64,
+    name: PathBuf,
+}
+
+impl DirectorySizes {
+    const FILE_NAME: &'static str = "directorysizes";
+
+    /// Reads the cache in `trash_dir`. Malformed lines are dropped
+    pub fn load(trash_dir: &Path) -> DirectorySizes {
+        let path = trash_dir.join(Self::FILE_NAME);
+        let entries = std::fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.splitn(3, ' ');
+                Some(DirectorySize {
+                    size: parts.next()?.parse().ok()?,
+                    mtime: parts.next()?.parse().ok()?,
+                    name: decode_path(parts.next()?),
+                })
+            })
+            .collect();
+
+        DirectorySizes { path, entries }
+    }
+
+    /// The cached size if the entry was written for the current info file
+    pub fn size(&self, trash_file_name: &Path, trash_info_path: &Path) -> Option<u64> {
+        let mtime = std::fs::metadata(trash_info_path).ok()?.mtime();
+        self.entries
+            .iter()
+            .find(|entry| entry.name == trash_file_name && entry.mtime == mtime)
+            .map(|entry| entry.size)
+    }
+
+    pub fn insert(&mut self, trash_file_name: &Path, size: u64, mtime: i64) {
+        self.remove(trash_file_name);
+        self.entries.push(DirectorySize {
+            size,
+            mtime,
+            name: trash_file_name.to_path_buf(),
+        });
+    }
+
+    pub fn remove(&mut self, trash_file_name: &Path) {
+        self.entries.retain(|entry| entry.name != trash_file_name);
+    }
+
+    /// Entries for directories that are gone or whose info file changed since the size was cached
+    pub fn stale_names(&self, trash_dirs: &TrashDirPaths) -> Vec<PathBuf> {
+        self.entries
+            .iter()
+            .filter(|entry| {
+                let trash_info_path = trash_dirs
+                    .trash_info_dir
+                    .join(TrashNames::from_trash_file_name(entry.name.clone()).trash_info_name);
+                let info_mtime = std::fs::metadata(trash_info_path).map(|m| m.mtime()).ok();
+                !trash_dirs.trash_files_dir.join(&entry.name).is_dir() || info_mtime != Some(entry.mtime)
+            })
+            .map(|entry| entry.name.clone())
+            .collect()
+    }
+
+    /// Writes a temporary file next to the cache and renames it over the cache
+    pub fn save(&self) -> io::Result<()> {
+        let content: String = self
+            .entries
+            .iter()
+            .map(|entry| format!("{} {} {}\n", entry.size, entry.mtime, encode_path(&entry.name)))
+            .collect();
+
+        let temp_path = self
+            .path
+            .with_file_name(format!(".{}.{}", Self::FILE_NAME, Uuid::new_v4()));
+        std::fs::write(&temp_path, content)?;
+        std::fs::rename(&temp_path, &self.path).inspect_err(|_| {
+            let _ = std::fs::remove_file(&temp_path);
+        })
+    }
+
+    /// Caches the size of a freshly trashed directory. Files are not cached
+    pub fn record(trash_file_path: &Path, trash_info_path: &Path) {
+        // a symlink to a directory is cached as the link it is
+        if !trash_file_path.symlink_metadata().is_ok_and(|m| m.is_dir()) {
+            return;
+        }
+        let (Some(trash_dir), Some(name)) = (trash_dir_of(trash_file_path), trash_file_path.file_name()) else {
+            return;
+        };
+
+        let size = file_ops::apparent_size(trash_file_path);
+        let mtime = std::fs::metadata(trash_info_path).map(|m| m.mtime());
+        if let (Ok(size), Ok(mtime)) = (size, mtime) {
+            let mut directory_sizes = Self::load(trash_dir);
+            directory_sizes.insert(Path::new(name), size, mtime);
+            directory_sizes.save_or_warn();
+        }
+    }
+
+    /// Drops the entry of an item that is no longer in the trash
+    pub fn forget(trash_file_path: &Path) {
+        let (Some(trash_dir), Some(name)) = (trash_dir_of(trash_file_path), trash_file_path.file_name()) else {
+            return;
+        };
+        let mut directory_sizes = Self::load(trash_dir);
+        if directory_sizes.entries.iter().any(|entry| entry.name == name) {
+            directory_sizes.remove(Path::new(name));
+            directory_sizes.save_or_warn();
+        }
+    }
+
+    pub fn clear(trash_dir: &Path) -> io::Result<()> {
+        match std::fs::remove_file(trash_dir.join(Self::FILE_NAME)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn save_or_warn(&self) {
+        if let Err(e) = self.save() {
+            output::warning(format!("Unable to update {}. {}", self.path.display(), e));
+        } else if GLOBAL.verbose() {
+            output::info(format!("Updated {}", self.path.display()));
+        }
+    }
+}
+
+/// The `directorysizes` caches of the trash cans looked at, each loaded once. Sizes walked on a miss are only
+/// written by [SizeCache::save], so a listing writes each cache at most once
+#[derive(Default)]
+pub struct SizeCache {
+    /// By trash dir, with whether entries were added
+    caches: HashMap<PathBuf, (DirectorySizes, bool)>,
+}
+
+impl SizeCache {
+    /// The cached size of a trashed directory. A stale or missing entry is walked and kept,
+    /// e.g. for directories trashed by other tools
+    pub fn size_of(&mut self, trash_file_path: &Path, trash_info_path: &Path) -> io::Result<u64> {
+        let (Some(trash_dir), Some(name)) = (trash_dir_of(trash_file_path), trash_file_path.file_name()) else {
+            return file_ops::apparent_size(trash_file_path);
+        };
+        let (directory_sizes, changed) = self
+            .caches
+            .entry(trash_dir.to_path_buf())
+            .or_insert_with(|| (DirectorySizes::load(trash_dir), false));
+        if let Some(size) = directory_sizes.size(Path::new(name), trash_info_path) {
+            return Ok(size);
+        }
+
+        let size = file_ops::apparent_size(trash_file_path)?;
+        if let Ok(mtime) = std::fs::metadata(trash_info_path).map(|m| m.mtime()) {
+            directory_sizes.insert(Path::new(name), size, mtime);
+            *changed = true;
+        }
+        Ok(size)
+    }
+
+    /// Writes the caches that gained entries
+    pub fn save(&self) {
+        for (directory_sizes, _) in self.caches.values().filter(|(_, changed)| *changed) {
+            // the cache is only a shortcut, a read-only trash can is still listed
+            let _ = directory_sizes.save();
+        }
+    }
+}
+
+/// `Trash/files/name` -> `Trash`
+fn trash_dir_of(trash_file_path: &Path) -> Option<&Path> {
+    trash_file_path.parent()?.parent()
+}
+
+#[test]
+fn round_trip() {
+    let trash_dir = std::env::temp_dir().join(format!("trash-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&trash_dir).unwrap();
+
+    let mut directory_sizes = DirectorySizes::load(&trash_dir);
+    directory_sizes.insert(Path::new("a dir"), 4096, 1654995669);
+    directory_sizes.insert(Path::new("other"), 1, 1);
+    directory_sizes.remove(Path::new("other"));
+    directory_sizes.save().unwrap();
+
+    assert_eq!(
+        "4096 1654995669 a%20dir\n",
+        std::fs::read_to_string(trash_dir.join("directorysizes")).unwrap()
+    );
+    let entries = DirectorySizes::load(&trash_dir).entries;
+    assert_eq!(PathBuf::from("a dir"), entries[0].name);
+
+    std::fs::remove_dir_all(trash_dir).unwrap();
+}
+
+#[test]
+fn caches_misses() {
+    let trash_dir = std::env::temp_dir().join(format!("trash-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(trash_dir.join("files/d")).unwrap();
+    std::fs::create_dir_all(trash_dir.join("info")).unwrap();
+    std::fs::write(trash_dir.join("files/d/file"), "contents").unwrap();
+    std::fs::write(trash_dir.join("info/d.trashinfo"), "").unwrap();
+    std::os::unix::fs::symlink("d", trash_dir.join("files/link")).unwrap();
+    std::fs::write(trash_dir.join("info/link.trashinfo"), "").unwrap();
+
+    let mut size_cache = SizeCache::default();
+    let size = size_cache
+        .size_of(&trash_dir.join("files/d"), &trash_dir.join("info/d.trashinfo"))
+        .unwrap();
+    // nothing is written until the cache is saved
+    assert!(DirectorySizes::load(&trash_dir).entries.is_empty());
+    size_cache.save();
+    DirectorySizes::record(&trash_dir.join("files/link"), &trash_dir.join("info/link.trashinfo"));
+
+    let entries = DirectorySizes::load(&trash_dir).entries;
+    assert_eq!(1, entries.len());
+    assert_eq!((PathBuf::from("d"), size), (entries[0].name.clone(), entries[0].size));
+
+    std::fs::remove_dir_all(trash_dir).unwrap();
+}
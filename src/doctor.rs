@@ -0,0 +1,395 @@
+use crate::{
+    directory_sizes::DirectorySizes, file_ops, output, trash_dir_paths::TrashDirPaths, trash_info::TrashInfo,
+    trash_names::TrashNames, GLOBAL,
+};
+use chrono::Local;
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use uuid::Uuid;
+
+/// How `trash doctor --fix` repairs a problem
+#[derive(Debug, Clone, Copy)]
+pub enum FixMode {
+    /// Write a new info file for trashed files without a valid one. Lone info files are deleted, bad names quarantined
+    Regenerate,
+    /// Move broken entries to Trash/quarantine
+    Quarantine,
+    /// Permanently delete broken entries
+    Delete,
+}
+
+impl FromStr for FixMode {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "regenerate" => Ok(FixMode::Regenerate),
+            "quarantine" => Ok(FixMode::Quarantine),
+            "delete" => Ok(FixMode::Delete),
+            _ => Err("Valid fix modes are `regenerate` `quarantine` `delete`"),
+        }
+    }
+}
+
+pub enum Problem {
+    /// A trashed file without an info file, e.g. the info file was deleted by hand
+    OrphanedFile(PathBuf),
+    /// An info file without a trashed file, e.g. a put that was killed before moving the file
+    OrphanedInfo(PathBuf),
+    CorruptInfo(PathBuf, String),
+    /// An entry whose name breaks the naming rules, so it can't be paired with a file or info file
+    BadName(PathBuf, &'static str),
+    /// A directorysizes entry for a directory that is gone or whose info file changed
+    StaleDirectorySize(PathBuf),
+}
+
+impl Problem {
+    fn kind(&self) -> &'static str {
+        match self {
+            Problem::OrphanedFile(_) => "orphaned-file",
+            Problem::OrphanedInfo(_) => "orphaned-info",
+            Problem::CorruptInfo(..) => "corrupt-info",
+            Problem::BadName(..) => "bad-name",
+            Problem::StaleDirectorySize(_) => "stale-directory-size",
+        }
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            Problem::OrphanedFile(p)
+            | Problem::OrphanedInfo(p)
+            | Problem::CorruptInfo(p, _)
+            | Problem::BadName(p, _)
+            | Problem::StaleDirectorySize(p) => p,
+        }
+    }
+}
+
+/// `<kind>\t<path>`, followed by `\t<reason>` for corrupt info files and bad names
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{}", self.kind(), self.path().display())?;
+        match self {
+            Problem::CorruptInfo(_, reason) => write!(f, "\t{}", reason),
+            Problem::BadName(_, reason) => write!(f, "\t{}", reason),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Reports the problems of every trash can and optionally fixes them. Exits 1 if any problem is left
+pub fn doctor(fix: Option<FixMode>) {
+    let mut found = 0;
+    let mut remaining = 0;
+
+    for trash_dirs in TrashDirPaths::all() {
+        for problem in diagnose(&trash_dirs) {
+            println!("{}", problem);
+            found += 1;
+
+            let Some(fix_mode) = fix else {
+                remaining += 1;
+                continue;
+            };
+            if let Err(e) = repair(&trash_dirs, &problem, fix_mode) {
+                output::error(format!("Could not fix {}. {}", problem.path().display(), e));
+                remaining += 1;
+            } else if GLOBAL.verbose() {
+                output::info(format!("Fixed {}", problem.path().display()));
+            }
+        }
+    }
+
+    match (found, remaining) {
+        (0, _) => output::info_stderr("No problems found"),
+        (_, 0) => output::info_stderr(format!("Fixed {} problems", found)),
+        _ => {
+            output::warning(format!("{} of {} problems left", remaining, found));
+            if fix.is_none() {
+                output::hint("Repair them with --fix <MODE>");
+            }
+            output::exit(1)
+        }
+    }
+}
+
+pub fn diagnose(trash_dirs: &TrashDirPaths) -> Vec<Problem> {
+    let mut problems = vec![];
+
+    for entry in fs::read_dir(&trash_dirs.trash_info_dir).into_iter().flatten().flatten() {
+        let trash_info_path = entry.path();
+        if let Some(reason) = TrashNames::info_name_problem(&entry.file_name()) {
+            problems.push(Problem::BadName(trash_info_path, reason));
+            continue;
+        }
+
+        let trash_names = TrashNames::from_trash_info_name(entry.file_name().into());
+        if trash_dirs
+            .trash_files_dir
+            .join(&trash_names.trash_file_name)
+            .symlink_metadata()
+            .is_err()
+        {
+            problems.push(Problem::OrphanedInfo(trash_info_path));
+        } else if let Err(e) = TrashInfo::from_file(&trash_info_path) {
+            problems.push(Problem::CorruptInfo(trash_info_path, e.to_string()));
+        }
+    }
+
+    for entry in fs::read_dir(&trash_dirs.trash_files_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        let trash_names = TrashNames::from_trash_file_name(entry.file_name().into());
+        if let Some(reason) = TrashNames::file_name_problem(&entry.file_name()) {
+            problems.push(Problem::BadName(entry.path(), reason));
+        } else if !trash_dirs.trash_info_dir.join(&trash_names.trash_info_name).exists() {
+            problems.push(Problem::OrphanedFile(entry.path()));
+        }
+    }
+
+    for name in DirectorySizes::load(&trash_dirs.trash_dir).stale_names(trash_dirs) {
+        problems.push(Problem::StaleDirectorySize(trash_dirs.trash_files_dir.join(name)));
+    }
+
+    problems.sort_by(|a, b| a.path().cmp(b.path()));
+    problems
+}
+
+fn repair(trash_dirs: &TrashDirPaths, problem: &Problem, fix_mode: FixMode) -> io::Result<()> {
+    match (problem, fix_mode) {
+        (Problem::StaleDirectorySize(trash_file_path), _) => {
+            let name = trash_file_path.file_name().expect("not empty");
+            let mut directory_sizes = DirectorySizes::load(&trash_dirs.trash_dir);
+            directory_sizes.remove(Path::new(name));
+            directory_sizes.save()?;
+
+            let trash_info_path = trash_dirs
+                .trash_info_dir
+                .join(TrashNames::from_trash_file_name(name.into()).trash_info_name);
+            if trash_info_path.exists() {
+                DirectorySizes::record(trash_file_path, &trash_info_path);
+            }
+            Ok(())
+        }
+        (Problem::OrphanedFile(trash_file_path), FixMode::Regenerate) => {
+            regenerate_info(trash_dirs, trash_file_path, false)
+        }
+        (Problem::CorruptInfo(trash_info_path, _), FixMode::Regenerate) => {
+            regenerate_info(trash_dirs, &trash_file_path_of(trash_dirs, trash_info_path), true)
+        }
+        (Problem::CorruptInfo(trash_info_path, _), FixMode::Quarantine) => {
+            quarantine(trash_dirs, &trash_file_path_of(trash_dirs, trash_info_path))?;
+            quarantine(trash_dirs, trash_info_path)
+        }
+        (Problem::CorruptInfo(trash_info_path, _), FixMode::Delete) => {
+            file_ops::remove_path(&trash_file_path_of(trash_dirs, trash_info_path))?;
+            fs::remove_file(trash_info_path)
+        }
+        // there is nothing to regenerate from
+        (Problem::OrphanedInfo(path), FixMode::Regenerate) => fs::remove_file(path),
+        (Problem::BadName(path, _), FixMode::Regenerate) => quarantine(trash_dirs, path),
+        (
+            Problem::OrphanedFile(path) | Problem::OrphanedInfo(path) | Problem::BadName(path, _),
+            FixMode::Quarantine,
+        ) => quarantine(trash_dirs, path),
+        (Problem::OrphanedFile(path) | Problem::OrphanedInfo(path) | Problem::BadName(path, _), FixMode::Delete) => {
+            file_ops::remove_path(path)
+        }
+    }
+}
+
+fn trash_file_path_of(trash_dirs: &TrashDirPaths, trash_info_path: &Path) -> PathBuf {
+    let trash_names = TrashNames::from_trash_info_name(trash_info_path.to_path_buf());
+    trash_dirs.trash_files_dir.join(trash_names.trash_file_name)
+}
+
+/// Writes an info file dated now with a best guess of the original path: the uuid-less name in the can's root.
+/// A corrupt info file is `replace`d only once the new one is complete, a missing one is never clobbered
+fn regenerate_info(trash_dirs: &TrashDirPaths, trash_file_path: &Path, replace: bool) -> io::Result<()> {
+    let trash_names = TrashNames::from_trash_file_name(trash_file_path.to_path_buf());
+    let original_name = TrashNames::strip_uuid(&trash_names.trash_file_name)
+        .map(|(file_basename, _)| file_basename)
+        .unwrap_or_else(|_| trash_names.trash_file_name.clone());
+
+    let trash_info = TrashInfo {
+        trash_file_name: trash_names.trash_file_name.clone(),
+        source_path: trash_dirs.root().join(original_name),
+        is_dir: trash_file_path.is_dir(),
+        deletion_date: Local::now(),
+        file_size: String::new(),
+    };
+    let content = trash_info.content(trash_dirs.topdir.as_deref());
+    let trash_info_path = trash_dirs.trash_info_dir.join(&trash_names.trash_info_name);
+
+    if replace {
+        let temp_path = trash_dirs.trash_info_dir.join(format!(
+            ".{}.{}",
+            trash_names.trash_info_name.display(),
+            Uuid::new_v4().simple()
+        ));
+        fs::write(&temp_path, content)?;
+        fs::rename(&temp_path, &trash_info_path).inspect_err(|_| {
+            let _ = fs::remove_file(&temp_path);
+        })?;
+    } else {
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&trash_info_path)
+            .and_then(|mut file| io::Write::write_all(&mut file, content.as_bytes()))?;
+    }
+    DirectorySizes::record(trash_file_path, &trash_info_path);
+    Ok(())
+}
+
+/// Moves an entry to Trash/quarantine, where no trash tool will look at it
+fn quarantine(trash_dirs: &TrashDirPaths, path: &Path) -> io::Result<()> {
+    let quarantine_dir = trash_dirs.trash_dir.join("quarantine");
+    fs::create_dir_all(&quarantine_dir)?;
+
+    let name = path.file_name().expect("not empty");
+    let mut destination = quarantine_dir.join(name);
+    if destination.symlink_metadata().is_ok() {
+        let mut unique_name = name.to_owned();
+        unique_name.push(format!(".{}", Uuid::new_v4()));
+        destination = quarantine_dir.join(unique_name);
+    }
+    fs::rename(path, destination)
+}
+
+/// A home trash can under a temp dir holding one healthy item and one of each problem
+#[cfg(test)]
+fn broken_trash_can() -> (PathBuf, TrashDirPaths) {
+    let (root, trash_dirs) = crate::trash_dir_paths::temp_trash_can(".local/share/Trash");
+    let info = |name: &str, content: &str| fs::write(trash_dirs.trash_info_dir.join(name), content).unwrap();
+    let file = |name: &str| fs::write(trash_dirs.trash_files_dir.join(name), "contents").unwrap();
+
+    file("healthy");
+    info(
+        "healthy.trashinfo",
+        "[Trash Info]\nPath=/home/me/healthy\nDeletionDate=2024-05-01T10:20:30\n",
+    );
+    file("orphan");
+    info(
+        "lonely.trashinfo",
+        "[Trash Info]\nPath=/home/me/lonely\nDeletionDate=2024-05-01T10:20:30\n",
+    );
+    file("corrupt");
+    info("corrupt.trashinfo", "garbage");
+    info("notes.txt", "");
+    info("..trashinfo", "");
+    file(&"a".repeat(250));
+    fs::write(trash_dirs.trash_dir.join("directorysizes"), "10 1 gone\n").unwrap();
+
+    (root, trash_dirs)
+}
+
+#[cfg(test)]
+fn repaired(fix_mode: FixMode) -> (PathBuf, TrashDirPaths) {
+    let (root, trash_dirs) = broken_trash_can();
+    for problem in diagnose(&trash_dirs) {
+        repair(&trash_dirs, &problem, fix_mode).unwrap();
+    }
+    assert!(diagnose(&trash_dirs).is_empty());
+    assert!(TrashInfo::from_file(&trash_dirs.trash_info_dir.join("healthy.trashinfo")).is_ok());
+    (root, trash_dirs)
+}
+
+#[cfg(test)]
+fn entries(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().chars().take(12).collect())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn diagnoses_problems() {
+    let (root, trash_dirs) = broken_trash_can();
+
+    let found: Vec<(&str, String)> = diagnose(&trash_dirs)
+        .iter()
+        .map(|problem| {
+            let name = problem.path().file_name().unwrap().to_string_lossy();
+            (problem.kind(), name.chars().take(12).collect())
+        })
+        .collect();
+    let expected = [
+        ("bad-name", "aaaaaaaaaaaa"),
+        ("stale-directory-size", "gone"),
+        ("orphaned-file", "orphan"),
+        ("bad-name", "..trashinfo"),
+        ("corrupt-info", "corrupt.tras"),
+        ("orphaned-info", "lonely.trash"),
+        ("bad-name", "notes.txt"),
+    ];
+    assert_eq!(expected.map(|(kind, name)| (kind, name.to_string())).to_vec(), found);
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn fix_regenerate() {
+    let (root, trash_dirs) = repaired(FixMode::Regenerate);
+
+    let regenerated = TrashInfo::from_file(&trash_dirs.trash_info_dir.join("orphan.trashinfo")).unwrap();
+    assert_eq!(root.join("orphan"), regenerated.source_path);
+    let regenerated = TrashInfo::from_file(&trash_dirs.trash_info_dir.join("corrupt.trashinfo")).unwrap();
+    assert_eq!(root.join("corrupt"), regenerated.source_path);
+    assert_eq!(
+        vec!["corrupt.tras", "healthy.tras", "orphan.trash"],
+        entries(&trash_dirs.trash_info_dir)
+    );
+    assert_eq!(
+        vec!["..trashinfo", "aaaaaaaaaaaa", "notes.txt"],
+        entries(&trash_dirs.trash_dir.join("quarantine"))
+    );
+    assert_eq!(
+        "",
+        fs::read_to_string(trash_dirs.trash_dir.join("directorysizes")).unwrap()
+    );
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn fix_quarantine() {
+    let (root, trash_dirs) = repaired(FixMode::Quarantine);
+
+    assert_eq!(vec!["healthy"], entries(&trash_dirs.trash_files_dir));
+    assert_eq!(vec!["healthy.tras"], entries(&trash_dirs.trash_info_dir));
+    assert_eq!(
+        vec![
+            "..trashinfo",
+            "aaaaaaaaaaaa",
+            "corrupt",
+            "corrupt.tras",
+            "lonely.trash",
+            "notes.txt",
+            "orphan"
+        ],
+        entries(&trash_dirs.trash_dir.join("quarantine"))
+    );
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn fix_delete() {
+    let (root, trash_dirs) = repaired(FixMode::Delete);
+
+    assert_eq!(vec!["healthy"], entries(&trash_dirs.trash_files_dir));
+    assert_eq!(vec!["healthy.tras"], entries(&trash_dirs.trash_info_dir));
+    assert!(!trash_dirs.trash_dir.join("quarantine").exists());
+
+    fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,182 @@
+use crate::{output, GLOBAL};
+use std::{
+    fs::{self, File, FileTimes, Metadata},
+    io,
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+/// Renames `from` to `to`, falling back to copy-then-delete when they are on different filesystems.
+/// The source is only removed once the copy is complete and checked.
+pub fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.raw_os_error() == Some(libc::EXDEV) => {
+            if GLOBAL.verbose() {
+                output::info(format!("{} is on another filesystem. Copying instead.", to.display()));
+            }
+            copy_then_remove(from, to)
+        }
+        result => result,
+    }
+}
+
+/// Copies `from` next to `to`, then renames the checked copy over `to`.
+/// A failed copy is cleaned up without touching a file `to` would have replaced
+fn copy_then_remove(from: &Path, to: &Path) -> io::Result<()> {
+    let temp = temp_sibling(to);
+    let copied = copy_recursive(from, &temp)
+        .and_then(|_| verify_copy(from, &temp))
+        .and_then(|_| fs::rename(&temp, to));
+    if let Err(e) = copied {
+        if temp.symlink_metadata().is_ok() {
+            let _ = remove_path(&temp);
+        }
+        return Err(e);
+    }
+
+    // the data is safe at `to` even if some of the source can't be removed
+    if let Err(e) = remove_path(from) {
+        output::warning(format!(
+            "Copied to {} but could not remove {}. {}",
+            to.display(),
+            from.display(),
+            e
+        ));
+    }
+    Ok(())
+}
+
+/// A hidden unused name in the directory of `path`, like `.name.<uuid>`
+fn temp_sibling(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.{}", name, Uuid::new_v4().simple()))
+}
+
+/// Removes a file, symlink or directory tree
+pub fn remove_path(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path)?.is_dir() {
+        true => fs::remove_dir_all(path),
+        false => fs::remove_file(path),
+    }
+}
+
+/// The apparent size in bytes of a file, symlink or directory tree, like `du -b`
+pub fn apparent_size(path: &Path) -> io::Result<u64> {
+    let metadata = fs::symlink_metadata(path)?;
+    let mut size = metadata.len();
+
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            size += apparent_size(&entry?.path())?;
+        }
+    }
+    Ok(size)
+}
+
+/// Copies a file, symlink or directory tree keeping permissions and timestamps
+fn copy_recursive(from: &Path, to: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(from)?;
+    let file_type = metadata.file_type();
+
+    if file_type.is_symlink() {
+        return std::os::unix::fs::symlink(fs::read_link(from)?, to);
+    }
+
+    if file_type.is_dir() {
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else if file_type.is_file() {
+        fs::copy(from, to)?;
+    } else {
+        return Err(io::Error::other(format!("Cannot copy special file {}", from.display())));
+    }
+
+    // set after the contents are written so a read-only dir can still be filled and its mtime isn't touched
+    copy_times(to, &metadata)?;
+    fs::set_permissions(to, metadata.permissions())
+}
+
+fn copy_times(to: &Path, metadata: &Metadata) -> io::Result<()> {
+    let times = FileTimes::new()
+        .set_accessed(metadata.accessed()?)
+        .set_modified(metadata.modified()?);
+    File::open(to)?.set_times(times)
+}
+
+/// Checks that both trees have the same entries, types, file sizes and link targets
+fn verify_copy(from: &Path, to: &Path) -> io::Result<()> {
+    let mismatch = || io::Error::other(format!("Copy of {} does not match the original", from.display()));
+    let from_metadata = fs::symlink_metadata(from)?;
+    let to_metadata = fs::symlink_metadata(to)?;
+
+    if from_metadata.file_type() != to_metadata.file_type() {
+        return Err(mismatch());
+    }
+
+    if from_metadata.is_symlink() {
+        if fs::read_link(from)? != fs::read_link(to)? {
+            return Err(mismatch());
+        }
+    } else if from_metadata.is_dir() {
+        let mut count = 0;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            verify_copy(&entry.path(), &to.join(entry.file_name()))?;
+            count += 1;
+        }
+        if fs::read_dir(to)?.count() != count {
+            return Err(mismatch());
+        }
+    } else if from_metadata.len() != to_metadata.len() {
+        return Err(mismatch());
+    }
+    Ok(())
+}
+
+#[test]
+fn copy_tree() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let root = std::env::temp_dir().join(format!("trash-test-{}", Uuid::new_v4()));
+    let from = root.join("from");
+    let to = root.join("to");
+    fs::create_dir_all(from.join("nested")).unwrap();
+    fs::write(from.join("nested/file.txt"), "contents").unwrap();
+    fs::set_permissions(from.join("nested/file.txt"), fs::Permissions::from_mode(0o600)).unwrap();
+    std::os::unix::fs::symlink("nested/file.txt", from.join("link")).unwrap();
+
+    copy_recursive(&from, &to).unwrap();
+    verify_copy(&from, &to).unwrap();
+
+    assert_eq!("contents", fs::read_to_string(to.join("link")).unwrap());
+    let (from_file, to_file) = (
+        fs::metadata(from.join("nested/file.txt")).unwrap(),
+        fs::metadata(to.join("nested/file.txt")).unwrap(),
+    );
+    assert_eq!(from_file.permissions().mode(), to_file.permissions().mode());
+    assert_eq!(from_file.modified().unwrap(), to_file.modified().unwrap());
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn failed_copy_keeps_destination() {
+    let root = std::env::temp_dir().join(format!("trash-test-{}", Uuid::new_v4()));
+    fs::create_dir_all(root.join("from")).unwrap();
+    fs::write(root.join("from/file"), "new").unwrap();
+    let fifo = std::ffi::CString::new(root.join("from/fifo").into_os_string().into_encoded_bytes()).unwrap();
+    // SAFETY: the path is a valid C string
+    assert_eq!(0, unsafe { libc::mkfifo(fifo.as_ptr(), 0o600) });
+    fs::write(root.join("to"), "existing").unwrap();
+
+    assert!(copy_then_remove(&root.join("from"), &root.join("to")).is_err());
+    assert_eq!("existing", fs::read_to_string(root.join("to")).unwrap());
+    assert!(root.join("from/file").is_file());
+    // no partial copy is left behind
+    assert_eq!(2, fs::read_dir(&root).unwrap().count());
+
+    fs::remove_dir_all(root).unwrap();
+}
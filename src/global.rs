@@ -1,3 +1,4 @@
+use crate::output::Format;
 use lazy_static::lazy_static;
 use std::sync::Mutex;
 
@@ -8,6 +9,7 @@ lazy_static! {
 pub struct Global {
     verbose: Mutex<bool>,
     force: Mutex<bool>,
+    format: Mutex<Format>,
 }
 
 impl Global {
@@ -15,6 +17,7 @@ impl Global {
         Global {
             verbose: Mutex::new(false),
             force: Mutex::new(false),
+            format: Mutex::new(Format::Text),
         }
     }
     pub fn verbose(&self) -> bool {
@@ -29,6 +32,12 @@ impl Global {
     pub fn set_force(&self, force: bool) {
         *self.force.lock().unwrap() = force
     }
+    pub fn format(&self) -> Format {
+        *self.format.lock().unwrap()
+    }
+    pub fn set_format(&self, format: Format) {
+        *self.format.lock().unwrap() = format
+    }
 }
 
 impl Default for Global {
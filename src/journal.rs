@@ -0,0 +1,226 @@
+use crate::{
+    output,
+    trash_dir_paths::TrashDirPaths,
+    trash_info::{decode_path, encode_path},
+};
+use chrono::{DateTime, Local};
+use lazy_static::lazy_static;
+use std::{
+    fmt,
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use uuid::Uuid;
+
+lazy_static! {
+    /// Every record written by one invocation shares this id
+    static ref OPERATION_ID: String = Uuid::new_v4().simple().to_string()[..8].to_string();
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OperationKind {
+    Put,
+    Restore,
+    Purge,
+    /// A directory a restore created for the restored file
+    CreateDir,
+    /// Reverses items of the operation with this id
+    Undo(String),
+}
+
+impl fmt::Display for OperationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OperationKind::Put => f.pad("put"),
+            OperationKind::Restore => f.pad("restore"),
+            OperationKind::Purge => f.pad("purge"),
+            OperationKind::CreateDir => f.pad("mkdir"),
+            OperationKind::Undo(id) => f.pad(&format!("undo:{}", id)),
+        }
+    }
+}
+
+impl FromStr for OperationKind {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "put" => Ok(OperationKind::Put),
+            "restore" => Ok(OperationKind::Restore),
+            "purge" => Ok(OperationKind::Purge),
+            "mkdir" => Ok(OperationKind::CreateDir),
+            _ => match s.strip_prefix("undo:") {
+                Some(id) if !id.is_empty() => Ok(OperationKind::Undo(id.to_string())),
+                _ => Err("Valid operations are `put` `restore` `purge` `mkdir` `undo:<id>`"),
+            },
+        }
+    }
+}
+
+/// One item of an operation. `path` is where the item came from (put, purge), went to (restore) or the directory
+/// created for it (mkdir)
+#[derive(Debug, PartialEq)]
+struct Record {
+    id: String,
+    time: DateTime<Local>,
+    kind: OperationKind,
+    cwd: PathBuf,
+    trash_file_path: PathBuf,
+    path: PathBuf,
+}
+
+impl Record {
+    /// `<id>\t<time>\t<kind>\t<cwd>\t<trash file path>\t<path>` with percent-encoded paths
+    fn line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\n",
+            self.id,
+            self.time.to_rfc3339(),
+            self.kind,
+            encode_path(&self.cwd),
+            encode_path(&self.trash_file_path),
+            encode_path(&self.path)
+        )
+    }
+
+    fn parse(line: &str) -> Option<Record> {
+        let mut fields = line.split('\t');
+        let record = Record {
+            id: fields.next()?.to_string(),
+            time: DateTime::parse_from_rfc3339(fields.next()?).ok()?.with_timezone(&Local),
+            kind: fields.next()?.parse().ok()?,
+            cwd: decode_path(fields.next()?),
+            trash_file_path: decode_path(fields.next()?),
+            path: decode_path(fields.next()?),
+        };
+        fields.next().is_none().then_some(record)
+    }
+}
+
+/// One item of an operation, see [Record]
+pub struct Item {
+    pub kind: OperationKind,
+    pub trash_file_path: PathBuf,
+    pub path: PathBuf,
+}
+
+/// The records of one invocation, in the order they were written.
+/// A restore that backs up existing files holds puts as well as restores
+pub struct Operation {
+    pub id: String,
+    pub time: DateTime<Local>,
+    pub cwd: PathBuf,
+    pub items: Vec<Item>,
+}
+
+impl Operation {
+    /// A restore if any item was restored, otherwise the kind of the first item
+    pub fn kind(&self) -> &OperationKind {
+        self.items
+            .iter()
+            .map(|item| &item.kind)
+            .find(|kind| **kind == OperationKind::Restore)
+            .unwrap_or(&self.items[0].kind)
+    }
+}
+
+/// An append-only log of puts, restores, purges and undos in `Trash/journal`, one line per item
+pub struct Journal {
+    path: PathBuf,
+    /// Written with every record of this journal
+    operation_id: String,
+}
+
+impl Journal {
+    const FILE_NAME: &'static str = "journal";
+
+    /// The journal of the home trash, recording this invocation's operation
+    pub fn home() -> Journal {
+        Journal {
+            path: TrashDirPaths::new().trash_dir.join(Self::FILE_NAME),
+            operation_id: OPERATION_ID.clone(),
+        }
+    }
+
+    /// Appends an item to the current operation. Failing to write only warns, the item was already handled
+    pub fn record(&self, kind: OperationKind, trash_file_path: &Path, path: &Path) {
+        let record = Record {
+            id: self.operation_id.clone(),
+            time: Local::now(),
+            kind,
+            cwd: std::env::current_dir().unwrap_or_default(),
+            trash_file_path: trash_file_path.to_path_buf(),
+            path: path.to_path_buf(),
+        };
+
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            // a single write so concurrent invocations don't interleave lines
+            .and_then(|mut file| file.write_all(record.line().as_bytes()));
+        if let Err(e) = written {
+            output::warning(format!("Unable to write {}. {}", self.path.display(), e));
+        }
+    }
+
+    /// Every operation, oldest first. Malformed lines are dropped
+    pub fn operations(&self) -> Vec<Operation> {
+        let content = std::fs::read_to_string(&self.path).unwrap_or_default();
+        let mut operations: Vec<Operation> = vec![];
+        for record in content.lines().filter_map(Record::parse) {
+            let item = Item {
+                kind: record.kind,
+                trash_file_path: record.trash_file_path,
+                path: record.path,
+            };
+            match operations.iter_mut().find(|operation| operation.id == record.id) {
+                Some(operation) => operation.items.push(item),
+                None => operations.push(Operation {
+                    id: record.id,
+                    time: record.time,
+                    cwd: record.cwd,
+                    items: vec![item],
+                }),
+            }
+        }
+        operations
+    }
+}
+
+#[cfg(test)]
+impl Journal {
+    /// A journal in `trash_dir` whose records share `operation_id`, standing in for one invocation
+    pub fn in_trash_dir(trash_dir: &Path, operation_id: &str) -> Journal {
+        Journal {
+            path: trash_dir.join(Self::FILE_NAME),
+            operation_id: operation_id.to_string(),
+        }
+    }
+}
+
+#[test]
+fn records() {
+    let record = Record {
+        id: "1a2b3c4d".to_string(),
+        time: DateTime::parse_from_rfc3339("2024-05-01T10:20:30+02:00")
+            .unwrap()
+            .with_timezone(&Local),
+        kind: OperationKind::Undo("5e6f7a8b".to_string()),
+        cwd: PathBuf::from("/home/me"),
+        trash_file_path: PathBuf::from("/home/me/.local/share/Trash/files/tab\tname"),
+        path: PathBuf::from("/home/me/tab\tname"),
+    };
+    let line = record.line();
+    assert_eq!(5, line.matches('\t').count());
+    assert_eq!(Some(record), Record::parse(line.trim_end()));
+
+    assert_eq!(None, Record::parse("1a2b3c4d\tyesterday\tput\t/\t/a\t/b"));
+    assert_eq!(
+        None,
+        Record::parse("1a2b3c4d\t2024-05-01T10:20:30+02:00\tmove\t/\t/a\t/b")
+    );
+    assert_eq!(None, Record::parse("1a2b3c4d\t2024-05-01T10:20:30+02:00\tput\t/\t/a"));
+}
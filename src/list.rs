@@ -0,0 +1,403 @@
+use crate::{
+    directory_sizes::SizeCache,
+    output,
+    trash_item::{ItemType, TrashItem},
+};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use colored::Colorize;
+use humansize::{file_size_opts as options, FileSize};
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum SortKey {
+    /// Newest first
+    Date,
+    /// Largest first
+    Size,
+    /// Original path, alphabetically
+    Path,
+}
+
+impl FromStr for SortKey {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "date" => Ok(SortKey::Date),
+            "size" => Ok(SortKey::Size),
+            "path" => Ok(SortKey::Path),
+            _ => Err("Valid sort keys are `date` `size` `path`"),
+        }
+    }
+}
+
+/// What `--glob` and `--regex` are matched against
+#[derive(Debug, Clone, Copy)]
+pub enum MatchOn {
+    /// The original path
+    Path,
+    /// The original file name or the trash name
+    Name,
+}
+
+impl FromStr for MatchOn {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "path" => Ok(MatchOn::Path),
+            "name" => Ok(MatchOn::Name),
+            _ => Err("Valid match targets are `path` `name`"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Pattern {
+    /// Has to match the whole path or name
+    Glob(glob::Pattern),
+    /// Has to match somewhere in the path or name
+    Regex(regex::Regex),
+}
+
+#[derive(Debug)]
+pub struct ItemPattern {
+    pub pattern: Pattern,
+    pub match_on: MatchOn,
+}
+
+impl ItemPattern {
+    pub fn matches(&self, item: &TrashItem) -> bool {
+        let source_path = &item.trash_info.source_path;
+        let candidates = match self.match_on {
+            MatchOn::Path => vec![source_path.to_string_lossy()],
+            MatchOn::Name => source_path
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .into_iter()
+                .chain([item.trash_info.trash_file_name.to_string_lossy()])
+                .collect(),
+        };
+
+        candidates.iter().any(|candidate| match &self.pattern {
+            Pattern::Glob(glob) => glob.matches(candidate),
+            Pattern::Regex(regex) => regex.is_match(candidate),
+        })
+    }
+}
+
+/// Which trashed items to show. Every set condition has to match
+#[derive(Debug, Default)]
+pub struct ListFilter {
+    /// Trashed from this directory or below
+    pub path_prefix: Option<PathBuf>,
+    pub pattern: Option<ItemPattern>,
+    pub item_type: Option<ItemType>,
+    pub since: Option<DateTime<Local>>,
+    pub until: Option<DateTime<Local>>,
+}
+
+impl ListFilter {
+    pub fn matches(&self, item: &TrashItem) -> bool {
+        let source_path = &item.trash_info.source_path;
+        let deletion_date = item.trash_info.deletion_date;
+
+        self.path_prefix
+            .as_ref()
+            .is_none_or(|prefix| source_path.starts_with(prefix))
+            && self.pattern.as_ref().is_none_or(|pattern| pattern.matches(item))
+            && self
+                .item_type
+                .is_none_or(|item_type| item.item_type() == Some(item_type))
+            && self.since.is_none_or(|since| deletion_date >= since)
+            && self.until.is_none_or(|until| deletion_date <= until)
+    }
+}
+
+/// Prints a table of the matching items in every trash can
+pub fn list(filter: &ListFilter, sort_key: SortKey, reverse: bool, pager: bool) {
+    // walking a directory is slow, so each size is looked up once for both the sort and the table
+    let with_sizes = matches!(sort_key, SortKey::Size) || !output::is_structured();
+    let mut size_cache = SizeCache::default();
+    let mut items: Vec<(TrashItem, Option<u64>)> = TrashItem::all()
+        .into_iter()
+        .filter(|item| filter.matches(item))
+        .map(|item| {
+            let size = if with_sizes { item.size(&mut size_cache) } else { None };
+            (item, size)
+        })
+        .collect();
+    if items.is_empty() {
+        output::info("No trashed items found");
+        return;
+    }
+
+    match sort_key {
+        SortKey::Date => items.sort_by_key(|(item, _)| std::cmp::Reverse(item.trash_info.deletion_date)),
+        SortKey::Size => items.sort_by_key(|(_, size)| std::cmp::Reverse(*size)),
+        SortKey::Path => items.sort_by(|(a, _), (b, _)| a.trash_info.source_path.cmp(&b.trash_info.source_path)),
+    }
+    if reverse {
+        items.reverse();
+    }
+
+    if output::is_structured() {
+        for (item, _) in &items {
+            output::emit(output::describe(
+                &item.trash_paths,
+                &item.trash_info,
+                Path::new(""),
+                &mut size_cache,
+            ));
+        }
+        size_cache.save();
+        return;
+    }
+    size_cache.save();
+
+    let table = table(&items);
+    if pager && io::stdout().is_terminal() {
+        if let Err(e) = page(&table) {
+            output::warning(format!("Unable to start the pager. {}", e));
+            print_ignoring_closed_pipe(&table);
+        }
+    } else {
+        print_ignoring_closed_pipe(&table);
+    }
+}
+
+/// `DELETED SIZE TYPE PATH TRASH NAME` with the columns padded to their widest value
+fn table(items: &[(TrashItem, Option<u64>)]) -> String {
+    let header = ["DELETED", "SIZE", "TYPE", "PATH", "TRASH NAME"].map(String::from);
+    let rows: Vec<[String; 5]> = items
+        .iter()
+        .map(|(item, size)| {
+            [
+                item.trash_info.deletion_date.format("%Y-%m-%d %H:%M:%S").to_string(),
+                size.map_or("-".to_string(), |size| size.file_size(options::CONVENTIONAL).unwrap()),
+                item.item_type()
+                    .map_or("-".to_string(), |item_type| item_type.to_string()),
+                item.trash_info.source_path.display().to_string(),
+                item.trash_info.trash_file_name.display().to_string(),
+            ]
+        })
+        .collect();
+
+    let mut widths = [0; 5];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |row: &[String; 5]| {
+        format!(
+            "{:<w0$}  {:>w1$}  {:<w2$}  {:<w3$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            row[4],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+        )
+    };
+
+    let mut table = format!("{}\n", format_row(&header).trim_end().yellow());
+    for row in &rows {
+        table.push_str(&format_row(row));
+        table.push('\n');
+    }
+    table
+}
+
+/// Pipes the output through `$PAGER`, `less -FRX` by default
+fn page(output: &str) -> io::Result<()> {
+    let pager = env::var("PAGER").unwrap_or_else(|_| "less -FRX".to_string());
+    let mut child = Command::new("sh").arg("-c").arg(&pager).stdin(Stdio::piped()).spawn()?;
+
+    let written = child.stdin.take().expect("stdin is piped").write_all(output.as_bytes());
+    child.wait()?;
+    match written {
+        // the pager was quit before reading everything
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+/// Like `print!`, without panicking when piped into e.g. `head`
+pub fn print_ignoring_closed_pipe(output: &str) {
+    let _ = io::stdout().lock().write_all(output.as_bytes());
+}
+
+/// `YYYY-MM-DD` is the start of the day
+pub fn parse_since(date: &str) -> Result<DateTime<Local>, String> {
+    parse_date(date, NaiveTime::MIN)
+}
+
+/// `YYYY-MM-DD` is the end of the day
+pub fn parse_until(date: &str) -> Result<DateTime<Local>, String> {
+    parse_date(date, NaiveTime::from_hms_opt(23, 59, 59).expect("valid time"))
+}
+
+/// Accepts a local `YYYY-MM-DDThh:mm:ss` or `YYYY-MM-DD` at `time_of_day`
+fn parse_date(date: &str, time_of_day: NaiveTime) -> Result<DateTime<Local>, String> {
+    let naive = NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDate::parse_from_str(date, "%Y-%m-%d").map(|day| day.and_time(time_of_day)))
+        .map_err(|_| format!("Expected YYYY-MM-DD or YYYY-MM-DDThh:mm:ss, found `{}`", date))?;
+
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(|| format!("Nonexistent local date {}", date))
+}
+
+/// Parses durations like `30d`, `1d12h` or `2 weeks`. A year is 365 days
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let invalid = || format!("Expected a duration like 30d, 12h or 1w2d, found `{}`", duration);
+    let mut total = Duration::zero();
+    let mut rest = duration.trim();
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let amount: i64 = rest[..digits].parse().map_err(|_| invalid())?;
+        rest = rest[digits..].trim_start();
+
+        let letters = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+        let unit = match &rest[..letters] {
+            "s" | "sec" | "second" | "seconds" => Duration::seconds(1),
+            "m" | "min" | "minute" | "minutes" => Duration::minutes(1),
+            "h" | "hour" | "hours" => Duration::hours(1),
+            "d" | "day" | "days" => Duration::days(1),
+            "w" | "week" | "weeks" => Duration::weeks(1),
+            "y" | "year" | "years" => Duration::days(365),
+            _ => return Err(invalid()),
+        };
+        rest = rest[letters..].trim_start();
+
+        total = i32::try_from(amount)
+            .ok()
+            .and_then(|amount| unit.checked_mul(amount))
+            .and_then(|amount| total.checked_add(&amount))
+            .ok_or_else(invalid)?;
+    }
+    Ok(total)
+}
+
+/// Parses sizes like `5G`, `500MB` or `1.5GiB`. Units are powers of 1024 and a bare number is bytes
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let invalid = || format!("Expected a size like 5G, 500MB or 1.5GiB, found `{}`", size);
+    let size = size.trim();
+    let digits = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let amount: f64 = size[..digits].parse().map_err(|_| invalid())?;
+
+    let exponent = match size[digits..].trim_start().to_ascii_uppercase().as_str() {
+        "" | "B" => 0,
+        "K" | "KB" | "KIB" => 1,
+        "M" | "MB" | "MIB" => 2,
+        "G" | "GB" | "GIB" => 3,
+        "T" | "TB" | "TIB" => 4,
+        _ => return Err(invalid()),
+    };
+    let bytes = amount * 1024_f64.powi(exponent);
+    match bytes < u64::MAX as f64 {
+        true => Ok(bytes as u64),
+        false => Err(invalid()),
+    }
+}
+
+#[test]
+fn filter_items() {
+    use crate::{trash_file_paths::AbsoluteTrashPaths, trash_info::TrashInfo};
+
+    let item = TrashItem {
+        trash_paths: AbsoluteTrashPaths {
+            trash_info_path: PathBuf::from("/nonexistent/Trash/info/foo.txt.trashinfo"),
+            trash_file_path: PathBuf::from("/nonexistent/Trash/files/foo.txt"),
+        },
+        trash_info: TrashInfo {
+            trash_file_name: PathBuf::from("foo.txt"),
+            source_path: PathBuf::from("/home/user/docs/foo.txt"),
+            is_dir: false,
+            deletion_date: parse_since("2022-06-11T21:01:09").unwrap(),
+            file_size: "0 B".to_string(),
+        },
+    };
+    let matches = |filter: ListFilter| filter.matches(&item);
+
+    assert!(matches(ListFilter::default()));
+    assert!(matches(ListFilter {
+        path_prefix: Some(PathBuf::from("/home/user")),
+        pattern: Some(ItemPattern {
+            pattern: Pattern::Glob(glob::Pattern::new("*.txt").unwrap()),
+            match_on: MatchOn::Name,
+        }),
+        since: Some(parse_since("2022-06-11").unwrap()),
+        until: Some(parse_until("2022-06-11").unwrap()),
+        ..ListFilter::default()
+    }));
+    assert!(!matches(ListFilter {
+        path_prefix: Some(PathBuf::from("/home/us")),
+        ..ListFilter::default()
+    }));
+    assert!(!matches(ListFilter {
+        pattern: Some(ItemPattern {
+            pattern: Pattern::Glob(glob::Pattern::new("*.rs").unwrap()),
+            match_on: MatchOn::Name,
+        }),
+        ..ListFilter::default()
+    }));
+    assert!(!matches(ListFilter {
+        until: Some(parse_until("2022-06-10").unwrap()),
+        ..ListFilter::default()
+    }));
+    let pattern = |pattern, match_on| ListFilter {
+        pattern: Some(ItemPattern { pattern, match_on }),
+        ..ListFilter::default()
+    };
+    assert!(matches(pattern(
+        Pattern::Glob(glob::Pattern::new("/home/*/foo.txt").unwrap()),
+        MatchOn::Path
+    )));
+    assert!(!matches(pattern(
+        Pattern::Glob(glob::Pattern::new("/home/*/foo.txt").unwrap()),
+        MatchOn::Name
+    )));
+    assert!(matches(pattern(
+        Pattern::Regex(regex::Regex::new(r"^fo+\.").unwrap()),
+        MatchOn::Name
+    )));
+    assert!(parse_since("June 11").is_err());
+}
+
+#[test]
+fn durations() {
+    assert_eq!(Duration::days(30), parse_duration("30d").unwrap());
+    assert_eq!(Duration::hours(36), parse_duration("1d12h").unwrap());
+    assert_eq!(Duration::weeks(2), parse_duration("2 weeks").unwrap());
+    assert!(parse_duration("30").is_err());
+    assert!(parse_duration("d").is_err());
+    assert!(parse_duration("").is_err());
+}
+
+#[test]
+fn sizes() {
+    assert_eq!(5 * 1024 * 1024 * 1024, parse_size("5G").unwrap());
+    assert_eq!(1536, parse_size("1.5 KiB").unwrap());
+    assert_eq!(100, parse_size("100").unwrap());
+    assert!(parse_size("5X").is_err());
+    assert!(parse_size("G").is_err());
+}
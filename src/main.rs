@@ -1,69 +1,138 @@
-use args::{Args, Method::*};
+use args::{Args, Command};
+use browse::ls;
 use clap::Parser;
-use colored::Colorize;
 use config::Config;
+use directory_sizes::SizeCache;
+use doctor::doctor;
 use global::GLOBAL;
-use trash::{empty, info, info_all, info_wild_card, put, restore};
+use list::{list, ListFilter};
+use trash::{
+    empty, empty_matching, empty_to_size, info, info_all, info_matching, info_wild_card, purge, put, restore,
+    restore_interactive, restore_matching,
+};
+use undo::{print_journal, undo};
 
 mod args;
+mod browse;
 mod config;
+mod directory_sizes;
+mod doctor;
+mod file_ops;
 mod global;
+mod journal;
+mod list;
+mod mount_points;
+mod output;
 mod trash;
 mod trash_dir_paths;
 mod trash_file_paths;
 mod trash_info;
+mod trash_item;
 mod trash_names;
+mod undo;
 
 /// ## Examples
 ///
+/// `trash doctor --fix quarantine` Reports and repairs broken trash entries.
+///
 /// `trash empty` Empties the trash directory.
 ///
-/// `trash info file` Reads the info of a file in ~/.local/share/Trash/files
+/// `trash empty --older-than 30d` Empties what was trashed more than 30 days ago.
+///
+/// `trash empty --keep-size 5G -n` Shows which of the oldest items would go to get the trash under 5 GB.
+///
+/// `trash info file` Reads the info of a file in $XDG_DATA_HOME/Trash/files (~/.local/share/Trash/files by default)
+///
+/// `trash info dir --tree` Also shows everything inside a trashed directory.
+///
+/// `trash ls dir src` Lists what is inside `src` of a trashed directory.
+///
+/// `trash list --sort size --name '*.log'` Shows the trashed logs, largest first.
+///
+/// `trash list --format ndjson` Prints one JSON object per trashed item for scripts.
+///
+/// `trash purge --regex '\.log$'` Permanently deletes trashed logs after asking.
 ///
 /// `trash put file -v` Trashes a file and prints verbose logs.
 ///
 /// `trash restore file -f` Restores a file, potentially forcing an overwrite.
-
+///
+/// `trash restore file --on-conflict rename` Restores next to an existing file as `file (restored YYYY-MM-DD)`.
+///
+/// `trash restore file --to /tmp` Restores a file into another directory.
+///
+/// `trash restore --here` Restores everything that was trashed from the current directory.
+///
+/// `trash restore` Picks the items to restore from a searchable list.
+///
+/// `trash undo` Reverses the last put or restore.
 fn main() {
     let args = Args::parse();
     GLOBAL.set_verbose(args.verbose);
-    GLOBAL.set_force(args.force);
 
-    match args.method {
-        Put => {
-            path_arg_guard(&args.files);
-            for user_path in args.files {
-                put(Config::new(&args.method, user_path))
+    match args.command {
+        Command::Put(put_args) => {
+            output::set_format(put_args.format);
+            for user_path in put_args.files {
+                let trash_paths = put(Config::for_put(user_path), put_args.naming);
+                if output::is_structured() {
+                    output::trashed(&trash_paths.trash_info_path, &mut SizeCache::default());
+                }
+            }
+        }
+        Command::Restore(restore_args) => {
+            output::set_format(restore_args.format);
+            let options = restore_args.options();
+            if let Some(filter) = restore_args.filter() {
+                restore_matching(&filter, &options);
+            } else if restore_args.files.is_empty() {
+                restore_interactive(&options);
+            }
+            for user_path in restore_args.files {
+                restore(&Config::for_restore(user_path).source_path, &options)
             }
         }
-        Restore => {
-            // todo. if no path, do an interactive restoration by showing the trash contents
-            path_arg_guard(&args.files);
-            for user_path in args.files {
-                restore(&Config::new(&args.method, user_path).source_path)
+        Command::List(list_args) => {
+            output::set_format(list_args.format);
+            list(&list_args.filter(), list_args.sort, list_args.reverse, list_args.pager)
+        }
+        Command::Purge(purge_args) => {
+            GLOBAL.set_force(purge_args.force);
+            purge(&purge_args.files, purge_args.filter().as_ref())
+        }
+        Command::Empty(empty_args) => {
+            GLOBAL.set_force(empty_args.force);
+            match (empty_args.keep_size, empty_args.filter()) {
+                (Some(limit), _) => empty_to_size(limit, empty_args.include_corrupt, empty_args.dry_run),
+                (None, Some(filter)) => empty_matching(&filter, empty_args.include_corrupt, empty_args.dry_run),
+                (None, None) if empty_args.dry_run => empty_matching(&ListFilter::default(), true, true),
+                (None, None) => empty(),
             }
         }
-        Empty => empty(),
-        Info => match args.files.len() {
-            0 => info_all(),
-            _ => {
-                for user_path in args.files {
-                    if args.all {
-                        info_wild_card(&user_path);
-                    } else {
-                        info(&user_path)
+        Command::Doctor(doctor_args) => doctor(doctor_args.fix),
+        Command::Undo(undo_args) => match undo_args.list {
+            true => print_journal(),
+            false => undo(undo_args.id.as_deref(), undo_args.naming),
+        },
+        Command::Ls(ls_args) => ls(&ls_args.item, ls_args.subpath.as_deref()),
+        Command::Info(info_args) => {
+            output::set_format(info_args.format);
+            match info_args.files.len() {
+                0 => match info_args.filter() {
+                    Some(filter) => info_matching(&filter, info_args.tree),
+                    None => info_all(info_args.tree),
+                },
+                _ => {
+                    for user_path in info_args.files {
+                        if info_args.all {
+                            info_wild_card(&user_path, info_args.tree);
+                        } else {
+                            info(&user_path, info_args.tree)
+                        }
                     }
                 }
             }
-        },
+        }
     };
-}
-
-pub fn path_arg_guard(user_path: &[String]) {
-    if user_path.is_empty() {
-        eprintln!("{} The following arguments are required:", "error:".red());
-        eprintln!("    {}\n", "[FILE]".green());
-        eprintln!("For more information try {}", "--help".green());
-        std::process::exit(1)
-    }
+    output::print_document();
 }
@@ -0,0 +1,85 @@
+use std::{
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+/// The mount point of the filesystem holding `path`, found by walking up until the device changes
+pub fn topdir(path: &Path) -> Option<PathBuf> {
+    let dev = std::fs::symlink_metadata(path).ok()?.dev();
+    let mut topdir = path;
+
+    while let Some(parent) = topdir.parent() {
+        if std::fs::metadata(parent).ok()?.dev() != dev {
+            break;
+        }
+        topdir = parent;
+    }
+    Some(topdir.to_path_buf())
+}
+
+/// Every mounted filesystem's mount point. Unreadable mount tables give an empty list.
+#[cfg(target_os = "linux")]
+pub fn mount_points() -> Vec<PathBuf> {
+    let mounts = match std::fs::read_to_string("/proc/self/mounts") {
+        Ok(m) => m,
+        Err(_) => return vec![],
+    };
+
+    mounts
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(unescape_mount_point)
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn mount_points() -> Vec<PathBuf> {
+    let output = match std::process::Command::new("mount").output() {
+        Ok(o) => o,
+        Err(_) => return vec![],
+    };
+
+    // `/dev/disk3s1 on /System/Volumes/Data (apfs, local, journaled)`
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once(" on ").map(|(_, rest)| rest))
+        .filter_map(|rest| rest.rsplit_once(" (").map(|(path, _)| PathBuf::from(path)))
+        .collect()
+}
+
+/// /proc/self/mounts escapes space, tab, newline and backslash as octal `\040` sequences
+#[cfg(target_os = "linux")]
+fn unescape_mount_point(escaped: &str) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+
+    let bytes = escaped.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let octal = bytes
+            .get(i + 1..i + 4)
+            .and_then(|o| std::str::from_utf8(o).ok())
+            .and_then(|o| u8::from_str_radix(o, 8).ok());
+        match (bytes[i], octal) {
+            (b'\\', Some(b)) => {
+                unescaped.push(b);
+                i += 4;
+            }
+            (b, _) => {
+                unescaped.push(b);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(std::ffi::OsString::from_vec(unescaped))
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn unescape() {
+    assert_eq!(
+        PathBuf::from("/media/usb stick\\x"),
+        unescape_mount_point("/media/usb\\040stick\\134x")
+    );
+}
@@ -0,0 +1,294 @@
+use crate::{
+    directory_sizes::SizeCache, file_ops, trash_dir_paths::TrashDirPaths, trash_file_paths::AbsoluteTrashPaths,
+    trash_info::TrashInfo, trash_item::ItemType, trash_names::TrashNames, GLOBAL,
+};
+use chrono::SecondsFormat;
+use colored::Colorize;
+use lazy_static::lazy_static;
+use serde_json::{json, Map, Value};
+use std::{
+    fmt::Display,
+    io::{self, Write},
+    path::Path,
+    str::FromStr,
+    sync::Mutex,
+};
+
+/// Bumped whenever a field is renamed, removed or changes meaning. Adding fields keeps the version
+pub const SCHEMA_VERSION: u32 = 1;
+
+lazy_static! {
+    /// Items waiting for the `json` document, which is printed once the command is done
+    static ref ITEMS: Mutex<Vec<Value>> = Mutex::new(vec![]);
+}
+
+/// How results and messages are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Coloured text for people
+    Text,
+    /// One `{"schema_version": 1, "items": [...]}` document
+    Json,
+    /// One item per line, each with its `schema_version`
+    Ndjson,
+}
+
+impl FromStr for Format {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            _ => Err("Valid formats are `text` `json` `ndjson`"),
+        }
+    }
+}
+
+/// Whether results are printed as json or ndjson instead of text
+pub fn is_structured() -> bool {
+    GLOBAL.format() != Format::Text
+}
+
+/// An item in the trash, or `subpath` inside a trashed directory:
+/// `trash_name` `original_path` `deleted_at` `size` (bytes) `type` and `trash_can`.
+/// `size` and `type` are null when the trashed file is missing
+pub fn describe(
+    trash_paths: &AbsoluteTrashPaths,
+    trash_info: &TrashInfo,
+    subpath: &Path,
+    size_cache: &mut SizeCache,
+) -> Value {
+    // joining an empty path would add a trailing slash
+    let inside = |path: &Path| match subpath.as_os_str().is_empty() {
+        true => path.to_path_buf(),
+        false => path.join(subpath),
+    };
+    let path = inside(&trash_paths.trash_file_path);
+    let item_type = path.symlink_metadata().ok().map(|metadata| match metadata.file_type() {
+        t if t.is_symlink() => ItemType::Link,
+        t if t.is_dir() => ItemType::Dir,
+        _ => ItemType::File,
+    });
+    let size = match item_type {
+        Some(ItemType::Dir) if subpath.as_os_str().is_empty() => {
+            size_cache.size_of(&path, &trash_paths.trash_info_path).ok()
+        }
+        Some(ItemType::Dir) => file_ops::apparent_size(&path).ok(),
+        Some(_) => path.symlink_metadata().ok().map(|metadata| metadata.len()),
+        None => None,
+    };
+    let trash_can = trash_paths.trash_info_path.parent().and_then(Path::parent);
+
+    json!({
+        "trash_name": trash_info.trash_file_name.to_string_lossy(),
+        "original_path": inside(&trash_info.source_path).to_string_lossy(),
+        "deleted_at": trash_info.deletion_date.to_rfc3339_opts(SecondsFormat::Secs, false),
+        "size": size,
+        "type": item_type.map(|item_type| item_type.to_string()),
+        "trash_can": trash_can.map(|path| path.to_string_lossy()),
+    })
+}
+
+/// Prints the item whose info file is at `trash_info_path`. Warns when the info can't be read
+pub fn trashed(trash_info_path: &Path, size_cache: &mut SizeCache) {
+    match describe_trashed(trash_info_path, size_cache) {
+        Ok(item) => emit(item),
+        Err(message) => warning(message),
+    }
+}
+
+fn describe_trashed(trash_info_path: &Path, size_cache: &mut SizeCache) -> Result<Value, String> {
+    let trash_paths = TrashDirPaths::containing(trash_info_path)
+        .zip(trash_info_path.file_name())
+        .map(|(trash_dirs, name)| AbsoluteTrashPaths::new(trash_dirs, TrashNames::from_trash_info_name(name.into())));
+    match (trash_paths, TrashInfo::from_file(trash_info_path)) {
+        (Some(trash_paths), Ok(trash_info)) => Ok(describe(&trash_paths, &trash_info, Path::new(""), size_cache)),
+        (_, Err(e)) => Err(format!("{:?} has been corrupted. {:?}", trash_info_path, e)),
+        (None, _) => Err(format!("{:?} is not in a trash can", trash_info_path)),
+    }
+}
+
+/// Prints a restored item's description with `restored_to`. `None` in text mode
+pub fn restored(item: Option<Value>, destination: &Path) {
+    if let Some(item) = item {
+        emit(with_restored_to(item, destination));
+    }
+}
+
+fn with_restored_to(mut item: Value, destination: &Path) -> Value {
+    item["restored_to"] = json!(destination.to_string_lossy());
+    item
+}
+
+/// Prints an ndjson line, or keeps the item for the json document. Text is printed by the callers
+pub fn emit(item: Value) {
+    match GLOBAL.format() {
+        Format::Text => {}
+        Format::Json => ITEMS.lock().unwrap().push(item),
+        Format::Ndjson => print_line(&versioned(item)),
+    }
+}
+
+pub fn set_format(format: Format) {
+    GLOBAL.set_format(format);
+}
+
+/// Prints the `json` document with the items handled so far. Called once the command is done, or by [exit]
+pub fn print_document() {
+    if GLOBAL.format() == Format::Json {
+        let items = std::mem::take(&mut *ITEMS.lock().unwrap());
+        print_line(&document(items));
+    }
+}
+
+/// Ends the process. The `json` document is printed first, so a command that bails on an error still reports
+/// the items it handled, and what was printed is flushed
+pub fn exit(code: i32) -> ! {
+    print_document();
+    let _ = io::stdout().flush();
+    let _ = io::stderr().flush();
+    std::process::exit(code)
+}
+
+fn document(items: Vec<Value>) -> Value {
+    json!({ "schema_version": SCHEMA_VERSION, "items": items })
+}
+
+pub fn info(message: impl Display) {
+    match is_structured() {
+        true => print_message("info", message),
+        false => println!("{} {}", "Info:".blue(), message),
+    }
+}
+
+/// An `Info:` about what wasn't done, on stderr
+pub fn info_stderr(message: impl Display) {
+    match is_structured() {
+        true => print_message("info", message),
+        false => eprintln!("{} {}", "Info:".blue(), message),
+    }
+}
+
+pub fn warning(message: impl Display) {
+    match is_structured() {
+        true => print_message("warning", message),
+        false => eprintln!("{} {}", "Warning:".yellow(), message),
+    }
+}
+
+pub fn hint(message: impl Display) {
+    match is_structured() {
+        true => print_message("hint", message),
+        false => eprintln!("{} {}", "Hint:".yellow(), message),
+    }
+}
+
+pub fn error(message: impl Display) {
+    match is_structured() {
+        true => print_message("error", message),
+        false => eprintln!("{} {}", "Err:".red(), message),
+    }
+}
+
+/// `{"schema_version": 1, "level": "error", "message": "..."}` on stderr, so stdout stays parseable
+fn print_message(level: &str, message: impl Display) {
+    let message = json!({ "level": level, "message": message.to_string() });
+    eprintln!("{}", versioned(message));
+}
+
+fn versioned(item: Value) -> Value {
+    let mut object = Map::new();
+    object.insert("schema_version".to_string(), json!(SCHEMA_VERSION));
+    if let Value::Object(fields) = item {
+        object.extend(fields);
+    }
+    Value::Object(object)
+}
+
+/// Like `println!`, without panicking when piped into e.g. `head`
+fn print_line(value: &Value) {
+    let _ = writeln!(io::stdout().lock(), "{}", value);
+}
+
+#[test]
+fn formats() {
+    assert_eq!(Ok(Format::Ndjson), "ndjson".parse());
+    assert!("yaml".parse::<Format>().is_err());
+
+    let item = versioned(json!({ "trash_name": "a.txt", "size": null }));
+    assert_eq!(
+        r#"{"schema_version":1,"trash_name":"a.txt","size":null}"#,
+        item.to_string()
+    );
+}
+
+/// A trash can holding `a.txt`, `d/sub/file` and the info of a `gone` file that is missing
+#[cfg(test)]
+fn described_trash_can() -> std::path::PathBuf {
+    let trash_dir = std::env::temp_dir().join(format!("trash-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(trash_dir.join("files/d/sub")).unwrap();
+    std::fs::create_dir_all(trash_dir.join("info")).unwrap();
+    std::fs::write(trash_dir.join("files/a.txt"), "contents").unwrap();
+    std::fs::write(trash_dir.join("files/d/sub/file"), "12345").unwrap();
+    for name in ["a.txt", "d", "gone"] {
+        std::fs::write(
+            trash_dir.join(format!("info/{}.trashinfo", name)),
+            format!(
+                "[Trash Info]\nPath=/home/me/{}\nDeletionDate=2024-05-01T10:20:30\n",
+                name
+            ),
+        )
+        .unwrap();
+    }
+    trash_dir
+}
+
+#[test]
+fn describes_items() {
+    let trash_dir = described_trash_can();
+    let size_cache = &mut SizeCache::default();
+
+    let item = describe_trashed(&trash_dir.join("info/a.txt.trashinfo"), size_cache).unwrap();
+    let fields: Vec<&String> = item.as_object().unwrap().keys().collect();
+    assert_eq!(
+        vec!["trash_name", "original_path", "deleted_at", "size", "type", "trash_can"],
+        fields
+    );
+    assert_eq!(json!("a.txt"), item["trash_name"]);
+    assert_eq!(json!("/home/me/a.txt"), item["original_path"]);
+    assert_eq!(json!(8), item["size"]);
+    assert_eq!(json!("file"), item["type"]);
+    assert_eq!(json!(trash_dir.to_string_lossy()), item["trash_can"]);
+
+    let gone = describe_trashed(&trash_dir.join("info/gone.trashinfo"), size_cache).unwrap();
+    assert_eq!((&Value::Null, &Value::Null), (&gone["size"], &gone["type"]));
+
+    let trash_paths = AbsoluteTrashPaths::new(
+        TrashDirPaths::containing(&trash_dir.join("info/d.trashinfo")).unwrap(),
+        TrashNames::from_trash_info_name("d.trashinfo".into()),
+    );
+    let trash_info = TrashInfo::from_file(&trash_paths.trash_info_path).unwrap();
+    let inside = describe(&trash_paths, &trash_info, Path::new("sub/file"), size_cache);
+    assert_eq!(json!("/home/me/d/sub/file"), inside["original_path"]);
+    assert_eq!(
+        (json!(5), json!("file")),
+        (inside["size"].clone(), inside["type"].clone())
+    );
+
+    let restored = with_restored_to(inside, Path::new("/home/me/d/sub/file"));
+    assert_eq!(json!("/home/me/d/sub/file"), restored["restored_to"]);
+
+    assert!(describe_trashed(&trash_dir.join("info/missing.trashinfo"), size_cache).is_err());
+    std::fs::remove_dir_all(trash_dir).unwrap();
+}
+
+#[test]
+fn json_document() {
+    let document = document(vec![json!({ "trash_name": "a.txt" })]);
+    assert_eq!(
+        r#"{"schema_version":1,"items":[{"trash_name":"a.txt"}]}"#,
+        document.to_string()
+    );
+}
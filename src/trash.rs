@@ -1,113 +1,1031 @@
 use crate::{
-    config::Config, trash_dir_paths::TrashDirPaths, trash_file_paths::AbsoluteTrashPaths, trash_info::TrashInfo,
-    trash_names::TrashNames, GLOBAL,
+    browse::print_tree,
+    config::{absolute_path, Config},
+    directory_sizes::{DirectorySizes, SizeCache},
+    file_ops,
+    journal::{Journal, OperationKind},
+    list::ListFilter,
+    output,
+    trash_dir_paths::TrashDirPaths,
+    trash_file_paths::{AbsoluteTrashPaths, AbsoluteTrashPathsError},
+    trash_info::TrashInfo,
+    trash_item::TrashItem,
+    trash_names::{Naming, TrashNames},
+    GLOBAL,
 };
+use chrono::{DateTime, Local, NaiveDate};
 use colored::Colorize;
-use std::path::{Path, PathBuf};
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use humansize::{file_size_opts as options, FileSize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-pub fn put(config: Config) {
-    let trash_names = TrashNames::from_base_name(config.file_basename);
-    let trash_file_name = trash_names.trash_file_name.clone();
-    let trash_paths = AbsoluteTrashPaths::new(TrashDirPaths::new(), trash_names);
-    let content = TrashInfo::new(config.source_path.clone(), trash_file_name).content();
-    trash_paths.write_info_file(content);
-    trash_paths.move_to_trash(&config.source_path);
+/// Moves a file to the trash and returns where it went
+pub fn put(config: Config, naming: Naming) -> AbsoluteTrashPaths {
+    match try_put(&config, naming, &TrashDirPaths::for_path(&config.source_path)) {
+        Ok(trash_paths) => {
+            Journal::home().record(OperationKind::Put, &trash_paths.trash_file_path, &config.source_path);
+            trash_paths
+        }
+        Err(e) => AbsoluteTrashPaths::clean_and_bail(e),
+    }
 }
 
-/// Print .trashinfo data if it exists
-pub fn info(user_path: &str) {
-    let trash_names = TrashNames::from_trash_file_name(PathBuf::from(user_path));
-    let trash_paths = AbsoluteTrashPaths::new(TrashDirPaths::new(), trash_names);
+/// Moves a file to the trash can `trash_dirs` and returns where it went.
+/// On `TrashFile` errors the info file is left to the caller
+pub fn try_put(
+    config: &Config,
+    naming: Naming,
+    trash_dirs: &TrashDirPaths,
+) -> Result<AbsoluteTrashPaths, AbsoluteTrashPathsError> {
+    let content =
+        TrashInfo::new(config.source_path.clone(), config.file_basename.clone()).content(trash_dirs.topdir.as_deref());
+    let trash_paths = AbsoluteTrashPaths::try_reserve(
+        trash_dirs,
+        TrashNames::candidates(naming, &config.file_basename),
+        &content,
+    )?;
+    let (trash_file_path, trash_info_path) = (trash_paths.trash_file_path.clone(), trash_paths.trash_info_path.clone());
+    trash_paths.try_move_to_trash(&config.source_path)?;
+    DirectorySizes::record(&trash_file_path, &trash_info_path);
+    Ok(AbsoluteTrashPaths {
+        trash_info_path,
+        trash_file_path,
+    })
+}
+
+/// Print .trashinfo data if it exists in any trash can
+pub fn info(user_path: &str, tree: bool) {
+    let trash_names = || TrashNames::from_trash_file_name(PathBuf::from(user_path));
+    let trash_paths = TrashDirPaths::all()
+        .into_iter()
+        .map(|trash_dirs| AbsoluteTrashPaths::new(trash_dirs, trash_names()))
+        .find(|trash_paths| trash_paths.trash_info_path.exists())
+        .unwrap_or_else(|| AbsoluteTrashPaths::new(TrashDirPaths::new(), trash_names()));
     trash_paths.guard_exists();
-    TrashInfo::read_to_std(&trash_paths.trash_info_path);
+    let mut size_cache = SizeCache::default();
+    read_info_to_std(&trash_paths.trash_info_path, tree, &mut size_cache);
+    size_cache.save();
 }
 
 /// Print all .trashinfo data with a matching file name
-pub fn info_wild_card(user_path: &str) {
+pub fn info_wild_card(user_path: &str, tree: bool) {
     let trash_names = TrashNames::from_trash_file_name(PathBuf::from(user_path));
-    let mut partial_name = AbsoluteTrashPaths::new(TrashDirPaths::new(), trash_names).trash_info_path;
+    let mut partial_name = trash_names.trash_info_name;
     partial_name.set_extension("");
     let user_path = partial_name.to_string_lossy().into_owned();
 
-    for trash_info_path in TrashDirPaths::new().get_all_info_paths() {
-        match trash_info_path {
-            Ok(p) => {
-                let p = p.path();
-                let info_path = p.to_string_lossy();
-                if info_path.contains(&user_path) {
-                    TrashInfo::read_to_std(&p)
+    let mut size_cache = SizeCache::default();
+    for trash_dirs in TrashDirPaths::all() {
+        for trash_info_path in trash_dirs.get_all_info_paths() {
+            match trash_info_path {
+                Ok(p) => {
+                    if p.file_name().to_string_lossy().contains(&user_path) {
+                        read_info_to_std(&p.path(), tree, &mut size_cache)
+                    }
                 }
-            }
-            Err(e) => {
-                eprintln!("{} Unable to read file: {}", "error:".red(), e);
-            }
-        };
+                Err(e) => {
+                    output::error(format!("Unable to read file: {}", e));
+                }
+            };
+        }
     }
+    size_cache.save();
 }
 
 /// Print all .trashinfo data
-pub fn info_all() {
-    for trash_info_path in TrashDirPaths::new().get_all_info_paths() {
-        match trash_info_path {
-            Ok(p) => {
-                println!();
-                TrashInfo::read_to_std(&p.path())
-            }
-            Err(e) => {
-                eprintln!("{} Unable to read file: {}", "error:".red(), e);
-            }
-        };
+pub fn info_all(tree: bool) {
+    let mut size_cache = SizeCache::default();
+    for trash_dirs in TrashDirPaths::all() {
+        for trash_info_path in trash_dirs.get_all_info_paths() {
+            match trash_info_path {
+                Ok(p) => {
+                    if !output::is_structured() {
+                        println!();
+                    }
+                    read_info_to_std(&p.path(), tree, &mut size_cache)
+                }
+                Err(e) => {
+                    output::error(format!("Unable to read file: {}", e));
+                }
+            };
+        }
+    }
+    size_cache.save();
+}
+
+/// Print the .trashinfo data of every item that matches the filter
+pub fn info_matching(filter: &ListFilter, tree: bool) {
+    let mut items: Vec<TrashItem> = TrashItem::all()
+        .into_iter()
+        .filter(|item| filter.matches(item))
+        .collect();
+    if items.is_empty() {
+        output::info("No trashed items match");
+        return;
+    }
+
+    items.sort_by(|a, b| a.trash_info.source_path.cmp(&b.trash_info.source_path));
+    let mut size_cache = SizeCache::default();
+    for item in items {
+        read_info_to_std(&item.trash_paths.trash_info_path, tree, &mut size_cache);
+    }
+    size_cache.save();
+}
+
+/// Prints the info and, with `tree`, the contents of a trashed directory
+fn read_info_to_std(trash_info_path: &Path, tree: bool, size_cache: &mut SizeCache) {
+    if output::is_structured() {
+        return output::trashed(trash_info_path, size_cache);
+    }
+    TrashInfo::read_to_std(trash_info_path, size_cache);
+
+    let trash_file_path = TrashDirPaths::containing(trash_info_path)
+        .zip(trash_info_path.file_name())
+        .map(|(trash_dirs, name)| {
+            AbsoluteTrashPaths::new(trash_dirs, TrashNames::from_trash_info_name(name.into())).trash_file_path
+        });
+    if let Some(trash_file_path) = trash_file_path.filter(|path| tree && path.is_dir()) {
+        print_tree(&trash_file_path);
+    }
+}
+
+/// Which item to restore when several were trashed from the same path
+#[derive(Debug, Clone, Copy)]
+pub enum DuplicateChoice {
+    Ask,
+    Latest,
+    Oldest,
+    All,
+}
+
+/// What to do when something already exists where an item is restored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    Fail,
+    Overwrite,
+    /// Restore as `foo (restored YYYY-MM-DD).txt`
+    Rename,
+    Skip,
+    /// Move the existing file to the trash first
+    Backup,
+    Ask,
+}
+
+impl FromStr for Conflict {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(Conflict::Fail),
+            "overwrite" => Ok(Conflict::Overwrite),
+            "rename" => Ok(Conflict::Rename),
+            "skip" => Ok(Conflict::Skip),
+            "backup" => Ok(Conflict::Backup),
+            "ask" => Ok(Conflict::Ask),
+            _ => Err("Valid conflict modes are `fail` `overwrite` `rename` `skip` `backup` `ask`"),
+        }
     }
 }
 
-/// Restore by Trash/files/base_name || Trash/info_base_name.trashinfo || source_path
-pub fn restore(path: &Path) {
-    let trash_dirs = TrashDirPaths::new();
+#[derive(Debug)]
+pub struct RestoreOptions {
+    pub duplicate_choice: DuplicateChoice,
+    pub conflict: Conflict,
+    /// Restore here instead of the original path. Items keep their original name inside an existing directory
+    pub destination: Option<PathBuf>,
+}
+
+/// Restore by Trash/files/base_name || Trash/info_base_name.trashinfo || source_path in any trash can
+pub fn restore(path: &Path, options: &RestoreOptions) {
+    let all_trash_dirs = TrashDirPaths::all();
+
+    let containing_trash_dirs = all_trash_dirs.iter().find(|trash_dirs| {
+        path.starts_with(&trash_dirs.trash_files_dir) || path.starts_with(&trash_dirs.trash_info_dir)
+    });
+
+    let chosen = if let Some(trash_dirs) = containing_trash_dirs {
+        // a path inside a trashed directory: Trash/files/<trash name>/<subpath>
+        if let Ok(relative) = path.strip_prefix(&trash_dirs.trash_files_dir) {
+            let mut components = relative.components();
+            let trash_name = components.next().map(|c| PathBuf::from(c.as_os_str()));
+            let subpath = components.as_path();
+            if let Some(trash_name) = trash_name.filter(|_| !subpath.as_os_str().is_empty()) {
+                let item = TrashItem::in_trash_dirs(trash_dirs)
+                    .into_iter()
+                    .find(|item| item.trash_info.trash_file_name == trash_name);
+                match item {
+                    Some(item) => return restore_subpath(&item, subpath, options),
+                    None => {
+                        output::info_stderr(format!("File not found in trash. {:?}", path));
+                        output::exit(1);
+                    }
+                }
+            }
+        }
 
-    let trash_paths = if path.starts_with(&trash_dirs.trash_files_dir) || path.starts_with(&trash_dirs.trash_info_dir) {
         // assume the path is in the info path, and change it if it's actually in the files dir
         let mut trash_info_path = path.to_owned();
         if path.starts_with(&trash_dirs.trash_files_dir) {
             let trash_info_name = TrashNames::from_trash_file_name(path.to_path_buf()).trash_info_name;
-            trash_info_path = trash_dirs.trash_info_dir;
+            trash_info_path = trash_dirs.trash_info_dir.clone();
             trash_info_path.push(trash_info_name);
         }
 
         let trash_paths = AbsoluteTrashPaths::new(
-            TrashDirPaths::new(),
+            trash_dirs.clone(),
             TrashNames::from_trash_info_name(trash_info_path.file_name().expect("not empty").into()),
         );
         trash_paths.guard_exists();
-        trash_paths
+        vec![trash_paths]
     } else {
-        match AbsoluteTrashPaths::find_by_source_path(path, &trash_dirs) {
-            Some(p) => p,
-            None => {
-                eprintln!("{} File not found in trash. {:?}", "Info:".blue(), path);
-                std::process::exit(1);
+        let matches: Vec<TrashItem> = all_trash_dirs
+            .iter()
+            .flat_map(|trash_dirs| TrashItem::find_by_source_path(path, trash_dirs))
+            .collect();
+        if matches.is_empty() {
+            return restore_from_trashed_ancestor(path, options);
+        }
+
+        choose_duplicates(path, matches, options.duplicate_choice)
+            .into_iter()
+            .map(|item| item.trash_paths)
+            .collect()
+    };
+
+    for trash_paths in chosen {
+        restore_trash_paths(&trash_paths, options);
+    }
+}
+
+/// Restore every item that matches the filter, parents before what was inside them
+pub fn restore_matching(filter: &ListFilter, options: &RestoreOptions) {
+    let mut by_source_path: BTreeMap<PathBuf, Vec<TrashItem>> = BTreeMap::new();
+    let mut count = 0;
+    for item in TrashItem::all() {
+        if filter.matches(&item) {
+            by_source_path
+                .entry(item.trash_info.source_path.clone())
+                .or_default()
+                .push(item);
+            count += 1;
+        }
+    }
+    if count == 0 {
+        output::info_stderr("No trashed items match");
+        output::exit(1);
+    }
+    output::info(format!("{} items match", count));
+
+    for (source_path, matches) in by_source_path {
+        for item in choose_duplicates(&source_path, matches, options.duplicate_choice) {
+            restore_trash_paths(&item.trash_paths, options);
+        }
+    }
+}
+
+/// Restores a path that was inside a directory when the directory was trashed
+fn restore_from_trashed_ancestor(path: &Path, options: &RestoreOptions) {
+    let ancestors: Vec<TrashItem> = TrashItem::all()
+        .into_iter()
+        .filter(|item| {
+            path.strip_prefix(&item.trash_info.source_path)
+                .is_ok_and(|subpath| item.path_inside(subpath).is_some())
+        })
+        .collect();
+    if ancestors.is_empty() {
+        output::info_stderr(format!("File not found in trash. {:?}", path));
+        output::exit(1);
+    }
+
+    for item in choose_duplicates(path, ancestors, options.duplicate_choice) {
+        let subpath = path.strip_prefix(&item.trash_info.source_path).expect("filtered above");
+        restore_subpath(&item, subpath, options);
+    }
+}
+
+/// Moves `subpath` out of a trashed directory, recreating its parent directories.
+/// The rest of the directory stays in the trash under the same info file
+fn restore_subpath(item: &TrashItem, subpath: &Path, options: &RestoreOptions) {
+    let Some(trash_file_path) = item.path_inside(subpath) else {
+        output::error(format!(
+            "{} is not in {}, or is behind a symlink",
+            subpath.display(),
+            item.trash_info.trash_file_name.display()
+        ));
+        output::exit(1)
+    };
+    let original_path = item.trash_info.source_path.join(subpath);
+
+    let missing_parent = match options.destination {
+        Some(_) => None,
+        None => original_path.parent().filter(|parent| !parent.is_dir()),
+    };
+    let destination = match missing_parent {
+        // nothing can be in the way inside a directory that doesn't exist yet
+        Some(_) => original_path.clone(),
+        None => match destination(&original_path, options) {
+            Some(destination) => destination,
+            None => return,
+        },
+    };
+    if GLOBAL.verbose() {
+        output::info(format!(
+            "Restoring {} from {} to {}",
+            subpath.display(),
+            item.trash_info.trash_file_name.display(),
+            destination.display()
+        ));
+    }
+
+    let record = output::is_structured()
+        .then(|| output::describe(&item.trash_paths, &item.trash_info, subpath, &mut SizeCache::default()));
+    if let Err(e) = move_out_of_trash(&Journal::home(), &trash_file_path, &destination) {
+        output::error(format!("Could not restore trashed file. {}", e));
+        output::exit(1);
+    }
+    output::restored(record, &destination);
+    // the directory got smaller
+    DirectorySizes::record(&item.trash_paths.trash_file_path, &item.trash_paths.trash_info_path);
+}
+
+/// Moves a file out of the trash and records the restore, creating the missing parent directories of
+/// `destination`. They are recorded first, so an undo moves the file back before removing them
+pub fn move_out_of_trash(journal: &Journal, trash_file_path: &Path, destination: &Path) -> Result<(), String> {
+    let mut created_dirs: Vec<PathBuf> = destination
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.symlink_metadata().is_err())
+        .map(Path::to_path_buf)
+        .collect();
+    created_dirs.reverse();
+    if let Some(parent) = created_dirs.last() {
+        if GLOBAL.verbose() {
+            output::info(format!("Creating {}", parent.display()));
+        }
+        std::fs::create_dir_all(parent).map_err(|e| format!("Unable to create {}. {}", parent.display(), e))?;
+    }
+    file_ops::move_path(trash_file_path, destination).map_err(|e| e.to_string())?;
+
+    for dir in &created_dirs {
+        journal.record(OperationKind::CreateDir, trash_file_path, dir);
+    }
+    journal.record(OperationKind::Restore, trash_file_path, destination);
+    Ok(())
+}
+
+/// The items trashed from `path` to restore, newest first
+fn choose_duplicates(path: &Path, mut matches: Vec<TrashItem>, duplicate_choice: DuplicateChoice) -> Vec<TrashItem> {
+    if matches.len() == 1 {
+        return matches;
+    }
+
+    // newest first
+    matches.sort_by_key(|item| std::cmp::Reverse(item.trash_info.deletion_date));
+
+    match duplicate_choice {
+        DuplicateChoice::Latest => vec![matches.remove(0)],
+        DuplicateChoice::Oldest => vec![matches.pop().expect("not empty")],
+        DuplicateChoice::All => matches,
+        DuplicateChoice::Ask => {
+            if !std::io::stdin().is_terminal() {
+                output::error(format!("{} items were trashed from {}", matches.len(), path.display()));
+                output::hint("Choose with --latest, --oldest or --all");
+                output::exit(1)
+            }
+
+            let mut size_cache = SizeCache::default();
+            let items: Vec<String> = matches
+                .iter()
+                .map(|item| {
+                    format!(
+                        "{}  {:>10}  {}",
+                        item.trash_info.deletion_date.format("%Y-%m-%d %H:%M:%S"),
+                        item.size(&mut size_cache).map_or("-".to_string(), human_size),
+                        item.trash_paths.trash_file_path.display()
+                    )
+                })
+                .collect();
+
+            let selection = dialoguer::Select::new()
+                .with_prompt(format!(
+                    "{} items were trashed from {}. Which would you like to restore?",
+                    matches.len(),
+                    path.display()
+                ))
+                .items(&items)
+                .default(0)
+                .interact_opt();
+
+            match selection {
+                Ok(Some(i)) => vec![matches.remove(i)],
+                Ok(None) => output::exit(0),
+                Err(e) => {
+                    output::error(format!("Unable to ask which item to restore. {}", e));
+                    output::hint("Choose with --latest, --oldest or --all");
+                    output::exit(1)
+                }
+            }
+        }
+    }
+}
+
+/// Restore items picked from everything in the trash whose original path fuzzy matches a typed filter.
+/// Space picks or unpicks an item, enter restores the picked ones
+pub fn restore_interactive(options: &RestoreOptions) {
+    if !std::io::stdin().is_terminal() {
+        output::error("Nothing to restore. No paths were given.");
+        output::hint("Pass the paths to restore, or run in a terminal to pick them");
+        output::exit(1)
+    }
+
+    let mut items = TrashItem::all();
+    if items.is_empty() {
+        output::info("The trash is empty");
+        output::exit(0);
+    }
+    // newest first
+    items.sort_by_key(|item| std::cmp::Reverse(item.trash_info.deletion_date));
+
+    let matcher = SkimMatcherV2::default();
+    let matches: Vec<&TrashItem> = loop {
+        let filter: String = match dialoguer::Input::new()
+            .with_prompt("Filter by original path, empty for everything")
+            .allow_empty(true)
+            .interact_text()
+        {
+            Ok(filter) => filter,
+            Err(e) => {
+                output::error(format!("Unable to ask which items to restore. {}", e));
+                output::exit(1)
             }
+        };
+        let matches: Vec<&TrashItem> = items
+            .iter()
+            .filter(|item| {
+                let source_path = item.trash_info.source_path.to_string_lossy();
+                matcher.fuzzy_match(&source_path, filter.trim()).is_some()
+            })
+            .collect();
+        match matches.is_empty() {
+            true => output::info(format!("Nothing in the trash matches {:?}", filter)),
+            false => break matches,
         }
     };
 
+    let mut size_cache = SizeCache::default();
+    let labels: Vec<String> = matches
+        .iter()
+        .map(|item| {
+            format!(
+                "{}  {:>8}  {:>10}",
+                item.trash_info.source_path.display(),
+                age(item.trash_info.deletion_date),
+                item.size(&mut size_cache).map_or("-".to_string(), human_size)
+            )
+        })
+        .collect();
+    // look-alikes get their trash name, so they can be told apart
+    let labels: Vec<String> = labels
+        .iter()
+        .zip(&matches)
+        .map(
+            |(label, item)| match labels.iter().filter(|other| *other == label).count() {
+                1 => label.clone(),
+                _ => format!("{}  {}", label, item.trash_info.trash_file_name.display()),
+            },
+        )
+        .collect();
+
+    let selection = dialoguer::MultiSelect::new()
+        .with_prompt("Space picks an item, enter restores the picked ones, esc cancels")
+        .items(&labels)
+        .report(false)
+        .interact_opt();
+    let picked = match selection {
+        Ok(Some(picked)) if !picked.is_empty() => picked,
+        Ok(_) => output::exit(0),
+        Err(e) => {
+            output::error(format!("Unable to ask which items to restore. {}", e));
+            output::exit(1)
+        }
+    };
+
+    for i in picked {
+        restore_trash_paths(&matches[i].trash_paths, options);
+    }
+}
+
+/// How long ago an item was trashed, e.g. `3d ago`
+fn age(deletion_date: DateTime<Local>) -> String {
+    let elapsed = Local::now().signed_duration_since(deletion_date);
+    match (elapsed.num_days(), elapsed.num_hours(), elapsed.num_minutes()) {
+        (d, _, _) if d >= 365 => format!("{}y ago", d / 365),
+        (d, _, _) if d >= 1 => format!("{}d ago", d),
+        (_, h, _) if h >= 1 => format!("{}h ago", h),
+        (_, _, m) if m >= 1 => format!("{}m ago", m),
+        _ => "just now".to_string(),
+    }
+}
+
+fn restore_trash_paths(trash_paths: &AbsoluteTrashPaths, options: &RestoreOptions) {
     let trash_info = match TrashInfo::from_file(&trash_paths.trash_info_path) {
         Ok(i) => i,
         Err(e) => {
-            eprintln!("{} .trashinfo file has been corrupted. {:?}", "Warning:".yellow(), e);
-            std::process::exit(1);
+            output::warning(format!(".trashinfo file has been corrupted. {:?}", e));
+            output::exit(1);
         }
     };
 
-    overwrite_guard(&trash_info.source_path);
-    trash_paths.restore_from_trash(&trash_info.source_path);
+    if let Some(destination) = destination(&trash_info.source_path, options) {
+        let record = output::is_structured()
+            .then(|| output::describe(trash_paths, &trash_info, Path::new(""), &mut SizeCache::default()));
+        trash_paths.restore_from_trash(&destination);
+        Journal::home().record(OperationKind::Restore, &trash_paths.trash_file_path, &destination);
+        output::restored(record, &destination);
+    }
+}
+
+/// Where an item from `original_path` is restored to, after `--to` and conflicts are taken into account.
+/// `None` skips the item. Bails when the parent directory is missing
+fn destination(original_path: &Path, options: &RestoreOptions) -> Option<PathBuf> {
+    let destination = match &options.destination {
+        Some(dir) if dir.is_dir() => dir.join(original_path.file_name().expect("not empty")),
+        Some(path) => path.clone(),
+        None => original_path.to_path_buf(),
+    };
+
+    if let Some(parent) = destination.parent().filter(|parent| !parent.is_dir()) {
+        output::error(format!("The directory {} does not exist", parent.display()));
+        if options.destination.is_none() {
+            output::hint("Restore somewhere else with --to <PATH>");
+        }
+        output::exit(1)
+    }
+
+    resolve_conflict(destination, options.conflict)
+}
+
+/// Where to restore to when `destination` may be taken. `None` skips the item
+fn resolve_conflict(destination: PathBuf, conflict: Conflict) -> Option<PathBuf> {
+    if destination.symlink_metadata().is_err() {
+        return Some(destination);
+    }
+
+    match conflict {
+        Conflict::Fail => {
+            output::error(format!("Will not overwrite file: {:?}", destination));
+            output::hint("Choose with --on-conflict rename|skip|backup|ask, or overwrite with -f");
+            output::exit(1)
+        }
+        Conflict::Overwrite => Some(destination),
+        Conflict::Rename => {
+            let today = Local::now().date_naive();
+            (1..)
+                .map(|n| restored_name(&destination, today, n))
+                .find(|path| path.symlink_metadata().is_err())
+        }
+        Conflict::Skip => {
+            output::info(format!("Skipped {}. It already exists", destination.display()));
+            None
+        }
+        Conflict::Backup => {
+            let file_basename = PathBuf::from(destination.file_name().expect("not empty"));
+            let backup = put(
+                Config {
+                    source_path: destination.clone(),
+                    file_basename,
+                },
+                Naming::Uuid,
+            );
+            if output::is_structured() {
+                output::trashed(&backup.trash_info_path, &mut SizeCache::default());
+            }
+            if GLOBAL.verbose() {
+                output::info(format!("Moved the existing {} to the trash", destination.display()));
+            }
+            Some(destination)
+        }
+        Conflict::Ask => resolve_conflict(destination.clone(), ask_conflict(&destination)),
+    }
+}
+
+fn ask_conflict(destination: &Path) -> Conflict {
+    if !std::io::stdin().is_terminal() {
+        output::error(format!("{} already exists", destination.display()));
+        output::hint("Choose with --on-conflict rename|skip|backup");
+        output::exit(1)
+    }
+
+    let choices = [
+        (Conflict::Rename, "Restore under a new name"),
+        (Conflict::Skip, "Skip"),
+        (Conflict::Backup, "Move the existing file to the trash, then restore"),
+        (Conflict::Overwrite, "Overwrite"),
+    ];
+    let selection = dialoguer::Select::new()
+        .with_prompt(format!("{} already exists", destination.display()))
+        .items(&choices.map(|(_, label)| label))
+        .default(0)
+        .interact_opt();
+
+    match selection {
+        Ok(Some(i)) => choices[i].0,
+        Ok(None) => output::exit(0),
+        Err(e) => {
+            output::error(format!("Unable to ask what to do. {}", e));
+            output::exit(1)
+        }
+    }
+}
+
+/// `foo (restored 2026-10-18).txt`, then `foo (restored 2026-10-18 2).txt` and so on
+fn restored_name(path: &Path, date: NaiveDate, n: usize) -> PathBuf {
+    let file_name = path.file_name().expect("not empty");
+    let mut restored_name = match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(_)) => stem.to_owned(),
+        _ => file_name.to_owned(),
+    };
+
+    restored_name.push(format!(" (restored {}", date.format("%Y-%m-%d")));
+    if n > 1 {
+        restored_name.push(format!(" {}", n));
+    }
+    restored_name.push(")");
+    if let Some(extension) = path.extension() {
+        restored_name.push(".");
+        restored_name.push(extension);
+    }
+    path.with_file_name(restored_name)
+}
+
+/// Permanently delete items by trash name, Trash/files or Trash/info path, original path, and those matching the filter
+pub fn purge(user_paths: &[String], filter: Option<&ListFilter>) {
+    let items = TrashItem::all();
+    let mut named: HashSet<PathBuf> = HashSet::new();
+    for user_path in user_paths {
+        match TrashItem::named(&items, user_path) {
+            Ok(matches) if matches.is_empty() => {
+                output::info_stderr(format!("File not found in trash. {:?}", absolute_path(user_path)));
+                output::exit(1);
+            }
+            Ok(matches) => named.extend(matches.iter().map(|item| item.trash_paths.trash_info_path.clone())),
+            Err(e) => {
+                output::error(e);
+                output::exit(1);
+            }
+        }
+    }
+
+    let chosen: Vec<TrashItem> = items
+        .into_iter()
+        .filter(|item| named.contains(&item.trash_paths.trash_info_path) || filter.is_some_and(|f| f.matches(item)))
+        .collect();
+    if chosen.is_empty() {
+        output::info("No trashed items match");
+        return;
+    }
+    output::info(format!("{} items match", chosen.len()));
+    purge_items(chosen, vec![], false);
+}
+
+/// Asks unless forced, then deletes the items and prints the space freed.
+/// `corrupt` are entries whose info file can't be read. A dry run only lists what would be deleted
+fn purge_items(items: Vec<TrashItem>, corrupt: Vec<AbsoluteTrashPaths>, dry_run: bool) {
+    let listed = !GLOBAL.force() || dry_run;
+    // with the original path, or the trash path when the info can't be read
+    let mut sized: Vec<(AbsoluteTrashPaths, PathBuf, u64)> = vec![];
+    let mut size_cache = SizeCache::default();
+    for item in items {
+        let size = item.size(&mut size_cache).unwrap_or(0);
+        if listed {
+            println!(
+                "{}  {:>10}  {}",
+                item.trash_info.deletion_date.format("%Y-%m-%d %H:%M:%S"),
+                human_size(size),
+                item.trash_info.source_path.display()
+            );
+        }
+        sized.push((item.trash_paths, item.trash_info.source_path, size));
+    }
+    for trash_paths in corrupt {
+        let size = file_ops::apparent_size(&trash_paths.trash_file_path).unwrap_or(0);
+        if listed {
+            println!(
+                "{:<19}  {:>10}  {}",
+                "corrupt info",
+                human_size(size),
+                trash_paths.trash_info_path.display()
+            );
+        }
+        let path = trash_paths.trash_file_path.clone();
+        sized.push((trash_paths, path, size));
+    }
+
+    if dry_run {
+        output::info(format!(
+            "Would free {} from {} items",
+            human_size(sized.iter().map(|(_, _, size)| size).sum()),
+            sized.len()
+        ));
+        return;
+    }
+
+    if !GLOBAL.force() {
+        let prompt = format!(
+            "{} Permanently delete {} items ({})?",
+            "Warn:".red(),
+            sized.len(),
+            human_size(sized.iter().map(|(_, _, size)| size).sum())
+        );
+        if !confirm(&prompt) {
+            output::exit(0);
+        }
+    }
+
+    let mut freed = 0;
+    let mut deleted = 0;
+    let mut failed = false;
+    for (trash_paths, path, size) in sized {
+        match trash_paths.purge() {
+            Ok(()) => {
+                Journal::home().record(OperationKind::Purge, &trash_paths.trash_file_path, &path);
+                freed += size;
+                deleted += 1;
+            }
+            Err(e) => {
+                output::error(format!(
+                    "Could not delete {}. {}",
+                    trash_paths.trash_file_path.display(),
+                    e
+                ));
+                failed = true;
+            }
+        }
+    }
+
+    output::info(format!("Freed {} from {} items", human_size(freed), deleted));
+    if failed {
+        output::exit(1);
+    }
+}
+
+/// Asks a yes/no question. Bails when there is no terminal to ask on
+fn confirm(prompt: &str) -> bool {
+    if !std::io::stdin().is_terminal() {
+        output::error("Will not delete without confirmation");
+        output::hint("Pass --force to delete without asking");
+        output::exit(1)
+    }
+
+    match dialoguer::Confirm::new().with_prompt(prompt).interact_opt() {
+        Ok(answer) => answer == Some(true),
+        Err(e) => {
+            output::error(format!("Unable to ask for confirmation. {}", e));
+            output::exit(1)
+        }
+    }
+}
+
+fn human_size(size: u64) -> String {
+    size.file_size(options::CONVENTIONAL).unwrap()
 }
 
 pub fn empty() {
     TrashDirPaths::empty();
 }
 
-pub fn overwrite_guard(path: &Path) {
-    if path.exists() && !GLOBAL.force() {
-        eprintln!("{} Will not overwrite file: {:?}", "Err:".red(), path);
-        std::process::exit(1)
+/// Permanently delete the items in every trash can that match the filter.
+/// Entries with an unreadable info file are only deleted with `include_corrupt`
+pub fn empty_matching(filter: &ListFilter, include_corrupt: bool, dry_run: bool) {
+    let all_trash_dirs = TrashDirPaths::all();
+    let items: Vec<TrashItem> = all_trash_dirs
+        .iter()
+        .flat_map(TrashItem::in_trash_dirs)
+        .filter(|item| filter.matches(item))
+        .collect();
+    let corrupt: Vec<AbsoluteTrashPaths> = match include_corrupt {
+        true => all_trash_dirs
+            .iter()
+            .flat_map(TrashItem::unreadable_in_trash_dirs)
+            .collect(),
+        false => vec![],
+    };
+
+    if items.is_empty() && corrupt.is_empty() {
+        output::info("Nothing to delete");
+        return;
+    }
+    purge_items(items, corrupt, dry_run);
+}
+
+/// Permanently delete the oldest items until the trash holds at most `limit` bytes.
+/// Entries with an unreadable info file are only counted and deleted, first, with `include_corrupt`
+pub fn empty_to_size(limit: u64, include_corrupt: bool, dry_run: bool) {
+    let all_trash_dirs = TrashDirPaths::all();
+    let mut items: Vec<TrashItem> = all_trash_dirs.iter().flat_map(TrashItem::in_trash_dirs).collect();
+    // oldest first
+    items.sort_by_key(|item| item.trash_info.deletion_date);
+    let mut size_cache = SizeCache::default();
+    let mut entries: Vec<(Result<TrashItem, AbsoluteTrashPaths>, u64)> = items
+        .into_iter()
+        .map(|item| {
+            let size = item.size(&mut size_cache).unwrap_or(0);
+            (Ok(item), size)
+        })
+        .collect();
+    if include_corrupt {
+        entries.extend(
+            all_trash_dirs
+                .iter()
+                .flat_map(TrashItem::unreadable_in_trash_dirs)
+                .map(|trash_paths| {
+                    let size = file_ops::apparent_size(&trash_paths.trash_file_path).unwrap_or(0);
+                    (Err(trash_paths), size)
+                }),
+        );
+    }
+
+    let total: u64 = entries.iter().map(|(_, size)| size).sum();
+    if total <= limit {
+        output::info(format!(
+            "The trash holds {}, which is within {}",
+            human_size(total),
+            human_size(limit)
+        ));
+        return;
     }
+
+    let chosen = over_limit_items(entries, limit);
+    let remaining = total - chosen.iter().map(|(_, size)| size).sum::<u64>();
+    let (items, corrupt): (Vec<_>, Vec<_>) = chosen.into_iter().map(|(entry, _)| entry).partition(Result::is_ok);
+    let items: Vec<TrashItem> = items.into_iter().flatten().collect();
+    let corrupt: Vec<AbsoluteTrashPaths> = corrupt.into_iter().filter_map(Result::err).collect();
+
+    if GLOBAL.verbose() || dry_run {
+        output::info(format!(
+            "The trash holds {}. {} will be left",
+            human_size(total),
+            human_size(remaining)
+        ));
+    }
+    purge_items(items, corrupt, dry_run);
+}
+
+/// The sized entries to delete so that the rest fit in `limit` bytes, taken in order from `entries` sorted
+/// oldest first. `Err` entries have an unreadable info file and go first, nothing tells how old they are
+fn over_limit_items<T, E>(mut entries: Vec<(Result<T, E>, u64)>, limit: u64) -> Vec<(Result<T, E>, u64)> {
+    // stable, the readable items stay oldest first
+    entries.sort_by_key(|(entry, _)| entry.is_ok());
+    let mut remaining: u64 = entries.iter().map(|(_, size)| size).sum();
+    entries
+        .into_iter()
+        .take_while(|(_, size)| {
+            let over = remaining > limit;
+            if over {
+                remaining -= size;
+            }
+            over
+        })
+        .collect()
+}
+
+#[test]
+fn restored_names() {
+    let date = NaiveDate::from_ymd_opt(2026, 10, 18).unwrap();
+    let restored = |path: &str, n| restored_name(Path::new(path), date, n);
+
+    assert_eq!(
+        PathBuf::from("/a/foo (restored 2026-10-18).txt"),
+        restored("/a/foo.txt", 1)
+    );
+    assert_eq!(
+        PathBuf::from("/a/foo (restored 2026-10-18 2).txt"),
+        restored("/a/foo.txt", 2)
+    );
+    assert_eq!(
+        PathBuf::from("/a/Makefile (restored 2026-10-18)"),
+        restored("/a/Makefile", 1)
+    );
+    assert_eq!(
+        PathBuf::from("/a/.bashrc (restored 2026-10-18)"),
+        restored("/a/.bashrc", 1)
+    );
+}
+
+#[test]
+fn restores_subpath() {
+    let (root, item) = crate::trash_item::trashed_dir_with_link();
+
+    let trash_file_path = item.path_inside(Path::new("sub/file")).unwrap();
+    let destination = item.trash_info.source_path.join("sub/file");
+    let journal = Journal::in_trash_dir(&root.join("Trash"), "rest0001");
+    move_out_of_trash(&journal, &trash_file_path, &destination).unwrap();
+
+    assert_eq!(
+        "contents",
+        std::fs::read_to_string(root.join("home/d/sub/file")).unwrap()
+    );
+    assert!(item.trash_paths.trash_file_path.join("sub").is_dir());
+    assert!(trash_file_path.symlink_metadata().is_err());
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn rejects_symlink_escape() {
+    let (root, item) = crate::trash_item::trashed_dir_with_link();
+
+    assert_eq!(None, item.path_inside(Path::new("link/victim")));
+    assert_eq!(None, item.path_inside(Path::new("../d/sub/file")));
+    assert_eq!(None, item.path_inside(Path::new("sub/missing")));
+    // the link itself can be restored, it is inside the item
+    assert!(item.path_inside(Path::new("link")).is_some());
+    assert_eq!("keep", std::fs::read_to_string(root.join("outside/victim")).unwrap());
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn backs_up_dangling_symlink() {
+    let (root, trash_dirs) = crate::trash_dir_paths::temp_trash_can("Trash");
+    // the restore destination is a link to nothing, which `--on-conflict backup` trashes first
+    let destination = root.join("link");
+    std::os::unix::fs::symlink(root.join("missing"), &destination).unwrap();
+
+    let config = Config {
+        source_path: destination.clone(),
+        file_basename: PathBuf::from("link"),
+    };
+    let backup = try_put(&config, Naming::Uuid, &trash_dirs).ok().unwrap();
+
+    assert!(destination.symlink_metadata().is_err());
+    assert_eq!(
+        root.join("missing"),
+        std::fs::read_link(&backup.trash_file_path).unwrap()
+    );
+    let trash_info = TrashInfo::from_file(&backup.trash_info_path).unwrap();
+    assert_eq!(destination, trash_info.source_path);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn keeps_size() {
+    // oldest first, 10 bytes each
+    let items = || -> Vec<(Result<&str, &str>, u64)> { vec![(Ok("a"), 10), (Ok("b"), 10), (Ok("c"), 10)] };
+    let chosen = |entries, limit| -> Vec<Result<&str, &str>> {
+        over_limit_items(entries, limit)
+            .into_iter()
+            .map(|(entry, _)| entry)
+            .collect()
+    };
+
+    assert!(chosen(items(), 30).is_empty());
+    assert!(chosen(items(), 100).is_empty());
+    assert_eq!(vec![Ok("a")], chosen(items(), 20));
+    assert_eq!(vec![Ok("a"), Ok("b")], chosen(items(), 19));
+    assert_eq!(vec![Ok("a"), Ok("b"), Ok("c")], chosen(items(), 0));
+
+    // with --include-corrupt, unreadable entries count towards the size and go first wherever they are listed
+    let mut entries = items();
+    entries.push((Err("corrupt"), 5));
+    assert_eq!(vec![Err("corrupt")], chosen(entries.clone(), 30));
+    assert_eq!(vec![Err("corrupt"), Ok("a")], chosen(entries, 29));
+}
+
+#[test]
+fn chooses_duplicates() {
+    let path = Path::new("/home/user/foo.txt");
+    let item = |trash_name: &str, deletion_date: &str| TrashItem {
+        trash_paths: AbsoluteTrashPaths {
+            trash_info_path: PathBuf::from(format!("/nonexistent/Trash/info/{}.trashinfo", trash_name)),
+            trash_file_path: PathBuf::from(format!("/nonexistent/Trash/files/{}", trash_name)),
+        },
+        trash_info: TrashInfo {
+            trash_file_name: PathBuf::from(trash_name),
+            source_path: path.to_path_buf(),
+            is_dir: false,
+            deletion_date: crate::list::parse_since(deletion_date).unwrap(),
+            file_size: "0 B".to_string(),
+        },
+    };
+    let chosen = |duplicate_choice| -> Vec<PathBuf> {
+        let matches = vec![item("old", "2022-06-11"), item("new", "2024-05-01")];
+        choose_duplicates(path, matches, duplicate_choice)
+            .into_iter()
+            .map(|item| item.trash_info.trash_file_name)
+            .collect()
+    };
+
+    assert_eq!(vec![PathBuf::from("new")], chosen(DuplicateChoice::Latest));
+    assert_eq!(vec![PathBuf::from("old")], chosen(DuplicateChoice::Oldest));
+    assert_eq!(
+        vec![PathBuf::from("new"), PathBuf::from("old")],
+        chosen(DuplicateChoice::All)
+    );
 }
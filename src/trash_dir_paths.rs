@@ -1,51 +1,190 @@
-use crate::GLOBAL;
+use crate::{directory_sizes::DirectorySizes, mount_points, output, GLOBAL};
 use colored::Colorize;
 use std::{
-    fs::ReadDir,
+    env,
+    fs::{DirBuilder, ReadDir},
     io,
+    os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt},
     path::{Path, PathBuf},
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 /// The absolute paths to the trash directory's file and info directories
 pub struct TrashDirPaths {
+    pub trash_dir: PathBuf,
     pub trash_files_dir: PathBuf,
     pub trash_info_dir: PathBuf,
+    /// The mount point of a per-volume trash can. `None` for the home trash.
+    pub topdir: Option<PathBuf>,
 }
 
 impl TrashDirPaths {
+    /// The home trash at `$XDG_DATA_HOME/Trash`, created if missing
     pub fn new() -> TrashDirPaths {
-        let home = match home::home_dir() {
-            None => {
-                eprintln!("{} Unable to get home directory.", "Err:".red());
-                std::process::exit(1);
-            }
-            Some(h) => h,
-        };
+        let trash_dir = Self::data_home().join("Trash");
+        let trash_paths = Self::from_trash_dir(trash_dir, None);
 
-        let trash_path: PathBuf = [&home, &PathBuf::from(".local/share/Trash")].iter().collect();
-        let trash_info_dir: PathBuf = [&home, &PathBuf::from(".local/share/Trash/info")].iter().collect();
-        let trash_files_dir: PathBuf = [&home, &PathBuf::from(".local/share/Trash/files")].iter().collect();
-
-        if !trash_info_dir.exists() | !trash_files_dir.exists() {
+        if !trash_paths.exists() {
             if GLOBAL.verbose() {
-                println!("{} Couldn't find Trash. Creating at {:?}", "Info:".blue(), trash_path);
+                output::info(format!("Couldn't find Trash. Creating at {:?}", trash_paths.trash_dir));
             }
-            if let Err(e) = Self::create_default_dirs(&trash_info_dir, &trash_files_dir) {
-                eprintln!("{} Unable to create Trash. {}", "Hint:".yellow(), e);
-                std::process::exit(1);
+            if let Err(e) = Self::create_default_dirs(&trash_paths.trash_info_dir, &trash_paths.trash_files_dir) {
+                output::hint(format!("Unable to create Trash. {}", e));
+                output::exit(1);
             };
         }
 
+        trash_paths
+    }
+
+    /// `$XDG_DATA_HOME`, or `~/.local/share` when it is unset, empty or relative
+    fn data_home() -> PathBuf {
+        match env::var_os("XDG_DATA_HOME").map(PathBuf::from) {
+            Some(data_home) if data_home.is_absolute() => data_home,
+            _ => match home::home_dir() {
+                Some(home) => home.join(".local/share"),
+                None => {
+                    output::error("Unable to get home directory.");
+                    output::exit(1);
+                }
+            },
+        }
+    }
+
+    pub(crate) fn from_trash_dir(trash_dir: PathBuf, topdir: Option<PathBuf>) -> TrashDirPaths {
         TrashDirPaths {
-            trash_files_dir,
-            trash_info_dir,
+            trash_files_dir: trash_dir.join("files"),
+            trash_info_dir: trash_dir.join("info"),
+            trash_dir,
+            topdir,
+        }
+    }
+
+    /// The trash can an info or trashed file lives in, working out the topdir from the trash dir's name
+    pub fn containing(trash_entry_path: &Path) -> Option<TrashDirPaths> {
+        let trash_dir = trash_entry_path.parent()?.parent()?;
+        let file_name = trash_dir.file_name()?.to_string_lossy();
+        let parent = trash_dir.parent();
+
+        let topdir = if file_name.starts_with(".Trash-") {
+            parent.map(Path::to_path_buf)
+        } else if parent.and_then(Path::file_name) == Some(".Trash".as_ref()) {
+            parent.and_then(Path::parent).map(Path::to_path_buf)
+        } else {
+            None
+        };
+
+        Some(Self::from_trash_dir(trash_dir.to_path_buf(), topdir))
+    }
+
+    /// Where trashed files are guessed to come from when their info file is lost: the topdir of a volume's
+    /// trash can, or the home directory for `~/.local/share/Trash`. A home trash moved by `$XDG_DATA_HOME`
+    /// guesses the directory holding it
+    pub fn root(&self) -> PathBuf {
+        if let Some(topdir) = &self.topdir {
+            return topdir.clone();
+        }
+        let data_home = self.trash_dir.parent().unwrap_or(&self.trash_dir);
+        match data_home.ends_with(".local/share") {
+            true => data_home.ancestors().nth(2).unwrap_or(data_home).to_path_buf(),
+            false => data_home.to_path_buf(),
+        }
+    }
+
+    fn exists(&self) -> bool {
+        self.trash_info_dir.is_dir() && self.trash_files_dir.is_dir()
+    }
+
+    /// The trash can a path should be moved to.
+    /// Paths on another filesystem than the home trash use their volume's trash can, falling back to the home trash.
+    pub fn for_path(source_path: &Path) -> TrashDirPaths {
+        let home_trash = Self::new();
+
+        let source_dev = std::fs::symlink_metadata(source_path).map(|m| m.dev());
+        let home_dev = std::fs::metadata(&home_trash.trash_dir).map(|m| m.dev());
+        if let (Ok(source_dev), Ok(home_dev)) = (source_dev, home_dev) {
+            if source_dev == home_dev {
+                return home_trash;
+            }
+        }
+
+        let topdir = match mount_points::topdir(source_path) {
+            Some(t) => t,
+            None => return home_trash,
+        };
+
+        for trash_paths in Self::topdir_candidates(&topdir) {
+            if trash_paths.exists() {
+                return trash_paths;
+            }
+            match Self::create_default_dirs(&trash_paths.trash_info_dir, &trash_paths.trash_files_dir) {
+                Ok(()) => {
+                    if GLOBAL.verbose() {
+                        output::info(format!("Created Trash at {:?}", trash_paths.trash_dir));
+                    }
+                    return trash_paths;
+                }
+                Err(e) => {
+                    if GLOBAL.verbose() {
+                        output::info(format!("Unable to create Trash at {:?}. {}", trash_paths.trash_dir, e));
+                    }
+                }
+            }
+        }
+
+        if GLOBAL.verbose() {
+            output::info(format!("Falling back to the home Trash for {:?}", topdir));
+        }
+        home_trash
+    }
+
+    /// `$topdir/.Trash/$uid` if `$topdir/.Trash` is a sticky directory and not a symlink, then `$topdir/.Trash-$uid`
+    fn topdir_candidates(topdir: &Path) -> Vec<TrashDirPaths> {
+        let uid = unsafe { libc::getuid() };
+        let mut candidates = vec![];
+
+        let shared_trash = topdir.join(".Trash");
+        if let Ok(metadata) = std::fs::symlink_metadata(&shared_trash) {
+            let is_sticky = metadata.permissions().mode() & 0o1000 != 0;
+            if metadata.is_dir() && is_sticky {
+                candidates.push(Self::from_trash_dir(
+                    shared_trash.join(uid.to_string()),
+                    Some(topdir.to_path_buf()),
+                ));
+            } else if GLOBAL.verbose() {
+                output::info(format!(
+                    "Ignoring {:?}. It must be a sticky directory and not a symlink.",
+                    shared_trash
+                ));
+            }
+        }
+
+        candidates.push(Self::from_trash_dir(
+            topdir.join(format!(".Trash-{}", uid)),
+            Some(topdir.to_path_buf()),
+        ));
+        candidates
+    }
+
+    /// The home trash and every existing trash can on a mounted volume
+    pub fn all() -> Vec<TrashDirPaths> {
+        let mut all = vec![Self::new()];
+
+        for topdir in mount_points::mount_points() {
+            for trash_paths in Self::topdir_candidates(&topdir) {
+                if trash_paths.exists() && !all.iter().any(|t| t.trash_dir == trash_paths.trash_dir) {
+                    all.push(trash_paths);
+                }
+            }
         }
+        all
     }
 
     fn create_default_dirs(trash_info_path: &Path, trash_files_path: &Path) -> io::Result<()> {
-        std::fs::create_dir_all(trash_info_path)?;
-        std::fs::create_dir_all(trash_files_path)?;
+        let mut builder = DirBuilder::new();
+        builder.recursive(true).mode(0o700);
+        builder.create(trash_info_path)?;
+        builder.create(trash_files_path)?;
         Ok(())
     }
 
@@ -55,54 +194,66 @@ impl TrashDirPaths {
         Ok(())
     }
 
-    /// Writes to stderr and bails on failure
+    fn count_entries(&self) -> usize {
+        let number_of_files = std::fs::read_dir(&self.trash_files_dir).map_or(0, |d| d.count());
+        match number_of_files {
+            0 => std::fs::read_dir(&self.trash_info_dir).map_or(0, |d| d.count()),
+            n => n,
+        }
+    }
+
+    /// Empties every trash can. Writes to stderr and bails on failure
     pub fn empty() {
-        let trash_path = TrashDirPaths::default();
-
-        let mut number_of_files = std::fs::read_dir(&trash_path.trash_files_dir)
-            .expect("Path created in default.")
-            .count();
-        if number_of_files == 0 {
-            number_of_files = std::fs::read_dir(&trash_path.trash_info_dir)
-                .expect("Path created in default.")
-                .count();
-        };
+        let trash_cans: Vec<TrashDirPaths> = Self::all()
+            .into_iter()
+            .filter(|trash_paths| trash_paths.count_entries() > 0 || GLOBAL.force())
+            .collect();
+        let number_of_files: usize = trash_cans.iter().map(Self::count_entries).sum();
+        let home_trash_files_dir = Self::new().trash_files_dir;
 
         if number_of_files == 0 && !GLOBAL.force() {
-            println!("{} {} is empty", "Info:".blue(), &trash_path.trash_files_dir.display());
-            std::process::exit(0);
+            output::info(format!("{} is empty", home_trash_files_dir.display()));
+            output::exit(0);
         }
 
         if !GLOBAL.force() {
+            let location = match trash_cans.as_slice() {
+                [trash_paths] => trash_paths.trash_files_dir.display().to_string(),
+                _ => format!("{} trash cans", trash_cans.len()),
+            };
             let answer = dialoguer::Confirm::new()
                 .with_prompt(format!(
                     "{} Permanently delete all {} files at {}?",
                     "Warn:".red(),
                     number_of_files,
-                    &trash_path.trash_files_dir.display()
+                    location
                 ))
                 .interact_opt()
                 .expect("User answer succeeds");
 
             match answer {
                 Some(true) => (),
-                _ => std::process::exit(0),
+                _ => output::exit(0),
             }
         }
 
-        if GLOBAL.verbose() {
-            println!(
-                "{} Deleting {} files in {:?}",
-                "Info:".blue(),
-                number_of_files,
-                &trash_path.trash_files_dir
-            );
-        }
+        for trash_paths in trash_cans {
+            if GLOBAL.verbose() {
+                output::info(format!(
+                    "Deleting {} files in {:?}",
+                    trash_paths.count_entries(),
+                    &trash_paths.trash_files_dir
+                ));
+            }
 
-        if let Err(e) = Self::remove_default_dirs(&trash_path.trash_info_dir, &trash_path.trash_files_dir) {
-            eprintln!("{} Unable to remove Trash. {}", "Err:".red(), e);
-        } else if let Err(e) = Self::create_default_dirs(&trash_path.trash_info_dir, &trash_path.trash_files_dir) {
-            eprintln!("{} Unable to recreate default Trash. {}", "Err:".red(), e);
+            if let Err(e) = Self::remove_default_dirs(&trash_paths.trash_info_dir, &trash_paths.trash_files_dir)
+                .and_then(|_| DirectorySizes::clear(&trash_paths.trash_dir))
+            {
+                output::error(format!("Unable to remove Trash. {}", e));
+            } else if let Err(e) = Self::create_default_dirs(&trash_paths.trash_info_dir, &trash_paths.trash_files_dir)
+            {
+                output::error(format!("Unable to recreate default Trash. {}", e));
+            }
         }
     }
 
@@ -116,3 +267,40 @@ impl Default for TrashDirPaths {
         Self::new()
     }
 }
+
+/// An empty trash can at `trash_dir` inside a new temp dir. The temp dir is returned for the test to remove
+#[cfg(test)]
+pub(crate) fn temp_trash_can(trash_dir: &str) -> (PathBuf, TrashDirPaths) {
+    let root = env::temp_dir().join(format!("trash-test-{}", uuid::Uuid::new_v4()));
+    let trash_dirs = TrashDirPaths::from_trash_dir(root.join(trash_dir), None);
+    std::fs::create_dir_all(&trash_dirs.trash_files_dir).unwrap();
+    std::fs::create_dir_all(&trash_dirs.trash_info_dir).unwrap();
+    (root, trash_dirs)
+}
+
+#[test]
+fn containing() {
+    let topdir = |path: &str| TrashDirPaths::containing(Path::new(path)).and_then(|t| t.topdir);
+
+    assert_eq!(
+        Some(PathBuf::from("/media/usb")),
+        topdir("/media/usb/.Trash-1000/info/foo.trashinfo")
+    );
+    assert_eq!(
+        Some(PathBuf::from("/media/usb")),
+        topdir("/media/usb/.Trash/1000/files/foo")
+    );
+    assert_eq!(None, topdir("/home/user/.local/share/Trash/info/foo.trashinfo"));
+}
+
+#[test]
+fn roots() {
+    let root = |path: &str| TrashDirPaths::containing(Path::new(path)).unwrap().root();
+
+    assert_eq!(PathBuf::from("/media/usb"), root("/media/usb/.Trash-1000/files/foo"));
+    assert_eq!(
+        PathBuf::from("/home/user"),
+        root("/home/user/.local/share/Trash/files/foo")
+    );
+    assert_eq!(PathBuf::from("/data/me"), root("/data/me/Trash/files/foo"));
+}
@@ -1,10 +1,11 @@
 use crate::{
-    global::GLOBAL, trash::overwrite_guard, trash_dir_paths::TrashDirPaths, trash_info::TrashInfo,
+    directory_sizes::DirectorySizes, file_ops, global::GLOBAL, output, trash_dir_paths::TrashDirPaths,
     trash_names::TrashNames,
 };
-use colored::Colorize;
 use std::{
-    fs,
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Write},
     path::{Path, PathBuf},
 };
 
@@ -22,6 +23,17 @@ pub enum AbsoluteTrashPathsError {
 
 pub type AbsoluteTrashPathsResult = Result<(), AbsoluteTrashPathsError>;
 
+impl fmt::Display for AbsoluteTrashPathsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AbsoluteTrashPathsError::WriteInfo(e) => write!(f, "Could not write info file. {}", e),
+            AbsoluteTrashPathsError::TrashFile(e, _) => write!(f, "Could not move trashed file. {}", e),
+            AbsoluteTrashPathsError::RestoreFile(e) => write!(f, "Could not restore trashed file. {}", e),
+            AbsoluteTrashPathsError::DeleteInfo(e) => write!(f, "Could not remove info file. {}", e),
+        }
+    }
+}
+
 impl AbsoluteTrashPaths {
     pub fn new(trash_paths: TrashDirPaths, trash_names: TrashNames) -> Self {
         let mut trash_info_path = trash_paths.trash_info_dir;
@@ -38,152 +50,167 @@ impl AbsoluteTrashPaths {
 
     pub fn guard_exists(&self) {
         if !self.trash_file_path.exists() {
-            eprintln!(
-                "{} Trash file path does not exist: {:?}",
-                "error:".red(),
-                self.trash_file_path
-            );
-            std::process::exit(1);
+            output::error(format!("Trash file path does not exist: {:?}", self.trash_file_path));
+            output::exit(1);
         }
         if !self.trash_info_path.exists() {
-            eprintln!(
-                "{} Trash info path does not exist: {:?}",
-                "error:".red(),
-                self.trash_info_path
-            );
-            std::process::exit(1);
+            output::error(format!("Trash info path does not exist: {:?}", self.trash_info_path));
+            output::exit(1);
         }
     }
 
-    // todo handle multiple files with the same source path
-    fn find_file_info(source_path: &Path, trash_info_dir: &Path) -> Option<PathBuf> {
-        let path_string = TrashInfo::format_source_path(source_path);
-
+    pub fn try_delete_info_file(&self) -> AbsoluteTrashPathsResult {
         if GLOBAL.verbose() {
-            println!(
-                "{} Checking trash info files for the path_string {}",
-                "Info:".blue(),
-                path_string
-            );
+            output::info(format!("Removing info file at {}", self.trash_info_path.display()));
         }
 
-        for entry in fs::read_dir(trash_info_dir).expect("Path checked in TrashDirPaths.") {
-            let entry = entry.ok()?;
-            let path = entry.path();
-            let extension = Path::new("e.trashinfo").extension();
-
-            if path.is_file() && path.extension() == extension {
-                let file = fs::read_to_string(&path).ok()?;
-                let mut lines = file.lines();
-                // move down to Path
-                lines.next();
-                lines.next();
-                if let Some(path_str) = lines.next() {
-                    if path_str == path_string {
-                        return Some(path);
-                    }
-                }
-            }
-        }
-        None
+        std::fs::remove_file(&self.trash_info_path).map_err(AbsoluteTrashPathsError::DeleteInfo)
     }
 
-    pub fn find_by_source_path(source_path: &Path, trash_path: &TrashDirPaths) -> Option<AbsoluteTrashPaths> {
-        let trash_info_path = AbsoluteTrashPaths::find_file_info(source_path, &trash_path.trash_info_dir)?;
-        let mut trash_file_path = trash_path.trash_files_dir.to_owned();
-        trash_file_path.push(trash_info_path.file_stem().expect("file has .trashinfo ext"));
-
-        Some(AbsoluteTrashPaths {
-            trash_info_path,
-            trash_file_path,
-        })
+    pub fn delete_info_file(&self) {
+        Self::clean_and_bail_on_error(self.try_delete_info_file());
     }
 
-    pub fn try_delete_info_file(&self) -> AbsoluteTrashPathsResult {
+    /// Permanently deletes the trashed file, then its info file
+    pub fn purge(&self) -> io::Result<()> {
         if GLOBAL.verbose() {
-            println!(
-                "{} Removing info file at {}",
-                "Info:".blue(),
-                self.trash_info_path.display()
-            );
+            output::info(format!("Deleting {}", self.trash_file_path.display()));
         }
 
-        std::fs::remove_file(&self.trash_info_path).map_err(AbsoluteTrashPathsError::DeleteInfo)
-    }
-
-    pub fn delete_info_file(&self) {
-        Self::clean_and_bail_on_error(self.try_delete_info_file());
+        if self.trash_file_path.symlink_metadata().is_ok() {
+            file_ops::remove_path(&self.trash_file_path)?;
+        }
+        fs::remove_file(&self.trash_info_path)?;
+        DirectorySizes::forget(&self.trash_file_path);
+        Ok(())
     }
 
     pub fn try_move_to_trash(self, source_path: &Path) -> AbsoluteTrashPathsResult {
         if GLOBAL.verbose() {
-            println!(
-                "{} Moving trashed file to {}",
-                "Info:".blue(),
-                self.trash_file_path.display()
-            );
+            output::info(format!("Moving trashed file to {}", self.trash_file_path.display()));
         }
 
-        std::fs::rename(source_path, &self.trash_file_path).map_err(|e| AbsoluteTrashPathsError::TrashFile(e, self))
+        file_ops::move_path(source_path, &self.trash_file_path).map_err(|e| AbsoluteTrashPathsError::TrashFile(e, self))
     }
 
-    /// Consumes self to clean up if necessary
-    pub fn move_to_trash(self, source_path: &Path) {
-        Self::clean_and_bail_on_error(self.try_move_to_trash(source_path));
-    }
+    /// Reserves the first free candidate name by creating its info file with `O_EXCL`, so concurrent puts
+    /// (or a file manager) can never clobber each other's info file.
+    /// Names whose info file or trashed file already exists are skipped.
+    pub fn try_reserve(
+        trash_dirs: &TrashDirPaths,
+        candidates: impl IntoIterator<Item = TrashNames>,
+        content: &str,
+    ) -> Result<AbsoluteTrashPaths, AbsoluteTrashPathsError> {
+        for trash_names in candidates {
+            let trash_paths = Self::new(trash_dirs.clone(), trash_names);
+            if trash_paths.trash_file_path.symlink_metadata().is_ok() {
+                continue;
+            }
 
-    pub fn try_write_info_file(&self, content: String) -> AbsoluteTrashPathsResult {
-        overwrite_guard(&self.trash_info_path);
+            let mut file = match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&trash_paths.trash_info_path)
+            {
+                Ok(f) => f,
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(AbsoluteTrashPathsError::WriteInfo(e)),
+            };
+
+            if GLOBAL.verbose() {
+                output::info(format!(
+                    "Writing info file to {}",
+                    trash_paths.trash_info_path.display()
+                ));
+            }
 
-        if GLOBAL.verbose() {
-            println!(
-                "{} Writing info file to {}",
-                "Info:".blue(),
-                self.trash_info_path.display()
-            );
+            if let Err(e) = file.write_all(content.as_bytes()) {
+                let _ = fs::remove_file(&trash_paths.trash_info_path);
+                return Err(AbsoluteTrashPathsError::WriteInfo(e));
+            }
+            return Ok(trash_paths);
         }
 
-        std::fs::write(&self.trash_info_path, content).map_err(AbsoluteTrashPathsError::WriteInfo)
-    }
-
-    pub fn write_info_file(&self, content: String) {
-        Self::clean_and_bail_on_error(self.try_write_info_file(content));
+        Err(AbsoluteTrashPathsError::WriteInfo(io::Error::other(
+            "no free trash name",
+        )))
     }
 
     pub fn clean_and_bail_on_error(maybe_error: AbsoluteTrashPathsResult) {
         if let Err(trash_paths_error) = maybe_error {
-            match trash_paths_error {
-                AbsoluteTrashPathsError::DeleteInfo(e) => {
-                    eprintln!("{} Could not remove info file. {:?}", "Err:".red(), e);
-                    std::process::exit(1)
-                }
-                AbsoluteTrashPathsError::WriteInfo(e) => {
-                    eprintln!("{} Could not write info file. {:?}", "Err:".red(), e);
-                    std::process::exit(1)
-                }
-                AbsoluteTrashPathsError::TrashFile(e, trash_paths) => {
-                    eprintln!("{} Could not move trashed file. {:?}", "Err:".red(), e);
-                    trash_paths.delete_info_file();
-                    std::process::exit(1)
-                }
-                AbsoluteTrashPathsError::RestoreFile(e) => {
-                    eprintln!("{} Could not restore trashed file. {:?}", "Err:".red(), e);
-                    std::process::exit(1)
-                }
+            Self::clean_and_bail(trash_paths_error)
+        }
+    }
+
+    /// Reports the error, removes the info file of a file that couldn't be trashed and exits
+    pub fn clean_and_bail(trash_paths_error: AbsoluteTrashPathsError) -> ! {
+        match trash_paths_error {
+            AbsoluteTrashPathsError::DeleteInfo(e) => {
+                output::error(format!("Could not remove info file. {:?}", e));
+            }
+            AbsoluteTrashPathsError::WriteInfo(e) => {
+                output::error(format!("Could not write info file. {:?}", e));
+            }
+            AbsoluteTrashPathsError::TrashFile(e, trash_paths) => {
+                output::error(format!("Could not move trashed file. {:?}", e));
+                trash_paths.delete_info_file();
+            }
+            AbsoluteTrashPathsError::RestoreFile(e) => {
+                output::error(format!("Could not restore trashed file. {:?}", e));
             }
         }
+        output::exit(1)
     }
 
     pub fn try_restore_from_trash(&self, source_path: &Path) -> AbsoluteTrashPathsResult {
         if GLOBAL.verbose() {
-            println!("{} Restoring trashed file to {}", "Info:".blue(), source_path.display());
+            output::info(format!("Restoring trashed file to {}", source_path.display()));
         }
 
-        std::fs::rename(&self.trash_file_path, source_path).map_err(AbsoluteTrashPathsError::RestoreFile)
+        file_ops::move_path(&self.trash_file_path, source_path).map_err(AbsoluteTrashPathsError::RestoreFile)
     }
 
     pub fn restore_from_trash(&self, source_path: &Path) {
         Self::clean_and_bail_on_error(self.try_restore_from_trash(source_path));
         self.delete_info_file();
+        DirectorySizes::forget(&self.trash_file_path);
+    }
+}
+
+#[test]
+fn concurrent_reservations() {
+    let (root, trash_dirs) = crate::trash_dir_paths::temp_trash_can("Trash");
+    fs::write(trash_dirs.trash_info_dir.join("foo.txt.trashinfo"), "taken").unwrap();
+
+    let handles: Vec<_> = (0..16)
+        .map(|i| {
+            let trash_dirs = trash_dirs.clone();
+            std::thread::spawn(move || {
+                let content = format!("put {}", i);
+                let candidates = TrashNames::candidates(crate::trash_names::Naming::Counter, Path::new("foo.txt"));
+                let trash_paths = AbsoluteTrashPaths::try_reserve(&trash_dirs, candidates, &content)
+                    .ok()
+                    .unwrap();
+                (trash_paths.trash_info_path, content)
+            })
+        })
+        .collect();
+
+    let mut reserved: Vec<PathBuf> = vec![];
+    for handle in handles {
+        let (trash_info_path, content) = handle.join().unwrap();
+        assert_eq!(content, fs::read_to_string(&trash_info_path).unwrap());
+        reserved.push(trash_info_path);
     }
+    reserved.sort();
+    reserved.dedup();
+
+    assert_eq!(16, reserved.len());
+    assert!(!reserved.contains(&trash_dirs.trash_info_dir.join("foo.txt.trashinfo")));
+    assert_eq!(
+        "taken",
+        fs::read_to_string(trash_dirs.trash_info_dir.join("foo.txt.trashinfo")).unwrap()
+    );
+
+    fs::remove_dir_all(root).unwrap();
 }
@@ -1,141 +1,173 @@
-use chrono::{DateTime, Utc};
+use crate::{directory_sizes::SizeCache, output, trash_dir_paths::TrashDirPaths};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
 use colored::Colorize;
 use humansize::{file_size_opts as options, FileSize};
-use std::path::{Path, PathBuf};
+use percent_encoding::{percent_decode_str, percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+/// Everything but the RFC 2396 unreserved characters and the path separator is escaped
+const PATH_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
 
 pub struct TrashInfo {
     pub trash_file_name: PathBuf,
     pub source_path: PathBuf,
     pub is_dir: bool,
-    pub deletion_date: DateTime<Utc>,
+    pub deletion_date: DateTime<Local>,
     pub file_size: String,
 }
 
 impl TrashInfo {
     const HEADER: &'static str = "[Trash Info]";
-    const FILENAME: &'static str = "FileName=";
-    const PATH: &'static str = "Path=";
-    const ISDIR: &'static str = "IsDir=";
-    const DELETIONDATE: &'static str = "DeletionDate=";
-    const FILESIZE: &'static str = "FileSize=";
+    const FILENAME: &'static str = "FileName";
+    const PATH: &'static str = "Path";
+    const ISDIR: &'static str = "IsDir";
+    const DELETIONDATE: &'static str = "DeletionDate";
+    const FILESIZE: &'static str = "FileSize";
+    const DATE_FORMAT: &'static str = "%Y-%m-%dT%H:%M:%S";
 
     pub fn new(source_path: PathBuf, trash_file_name: PathBuf) -> Self {
-        let file_size = std::fs::metadata(&source_path).expect("TODO").len();
-        let file_size = file_size.file_size(options::CONVENTIONAL).unwrap();
         let is_dir = source_path.is_dir();
 
+        // the size isn't written to the info file, and is worked out from the trashed file when shown
         TrashInfo {
             trash_file_name,
             source_path,
             is_dir,
-            deletion_date: std::time::SystemTime::now().into(),
-            file_size,
+            deletion_date: Local::now(),
+            file_size: String::new(),
         }
     }
 
-    pub fn content(&self) -> String {
-        self.color_content(false)
-    }
+    /// The .trashinfo file content as defined by the FreeDesktop.org Trash specification.
+    /// `Path=` is made relative to a trash can's topdir, so the trash survives the volume being remounted
+    pub fn content(&self, topdir: Option<&Path>) -> String {
+        let source_path = topdir
+            .and_then(|topdir| self.source_path.strip_prefix(topdir).ok())
+            .filter(|relative| !relative.as_os_str().is_empty())
+            .unwrap_or(&self.source_path);
 
-    pub fn format_source_path(source_path: &Path) -> String {
-        format!("{}{}", TrashInfo::PATH, source_path.display())
+        format!(
+            "{}\n{}={}\n{}={}\n",
+            TrashInfo::HEADER,
+            TrashInfo::PATH,
+            encode_path(source_path),
+            TrashInfo::DELETIONDATE,
+            self.deletion_date.format(TrashInfo::DATE_FORMAT),
+        )
     }
-    fn color_format_source_path(source_path: &Path) -> String {
-        format!("{}{}", TrashInfo::PATH, source_path.display())
+
+    fn color_content(&self) -> String {
+        format!(
+            "{}\n{}={}\n{}={}\n{}={}\n{}={}\n{}={}",
+            TrashInfo::HEADER.yellow(),
+            TrashInfo::FILENAME,
+            self.trash_file_name.display(),
+            TrashInfo::PATH,
+            self.source_path.display(),
+            TrashInfo::ISDIR,
+            self.is_dir,
+            TrashInfo::DELETIONDATE,
+            self.deletion_date.format(TrashInfo::DATE_FORMAT),
+            TrashInfo::FILESIZE,
+            self.file_size,
+        )
     }
 
-    fn color_content(&self, color: bool) -> String {
-        match color {
-            true => {
-                format!(
-                    "{}\n{}{}\n{}\n{}{}\n{}{}\n{}{}",
-                    TrashInfo::HEADER.yellow(),
-                    TrashInfo::FILENAME,
-                    self.trash_file_name.display(),
-                    Self::color_format_source_path(&self.source_path),
-                    TrashInfo::ISDIR,
-                    self.is_dir,
-                    TrashInfo::DELETIONDATE,
-                    self.deletion_date.to_rfc3339(),
-                    TrashInfo::FILESIZE,
-                    self.file_size,
-                )
-            }
-            false => {
-                format!(
-                    "{}\n{}{}\n{}\n{}{}\n{}{}\n{}{}",
-                    TrashInfo::HEADER,
-                    TrashInfo::FILENAME,
-                    self.trash_file_name.display(),
-                    Self::format_source_path(&self.source_path),
-                    TrashInfo::ISDIR,
-                    self.is_dir,
-                    TrashInfo::DELETIONDATE,
-                    self.deletion_date.to_rfc3339(),
-                    TrashInfo::FILESIZE,
-                    self.file_size,
-                )
+    pub fn read_to_std(trash_info_path: &Path, size_cache: &mut SizeCache) {
+        if trash_info_path.is_file() {
+            let mut trash_info = match TrashInfo::from_file(trash_info_path) {
+                Ok(trash_info) => trash_info,
+                Err(e) => {
+                    output::warning(format!("{:?} has been corrupted. {:?}", trash_info_path, e));
+                    return;
+                }
+            };
+            // only walked for the directories that are shown
+            let trash_file_path = trash_info_path
+                .parent()
+                .and_then(Path::parent)
+                .map(|trash_dir| trash_dir.join("files").join(&trash_info.trash_file_name));
+            if let Some(trash_file_path) = trash_file_path.filter(|_| trash_info.is_dir) {
+                if let Ok(size) = size_cache.size_of(&trash_file_path, trash_info_path) {
+                    trash_info.file_size = size.file_size(options::CONVENTIONAL).unwrap();
+                }
             }
+            println!("\n{}", trash_info.color_content());
         }
     }
 
-    pub fn read_to_std(trash_info_path: &Path) {
-        if trash_info_path.is_file() {
-            let trash_info = TrashInfo::from_file(trash_info_path);
-            if let Err(e) = trash_info {
-                eprintln!(
-                    "{} {:?} has been corrupted. {:?}",
-                    "Warning:".yellow(),
-                    trash_info_path,
-                    e
-                );
-                return;
-            }
-            println!("\n{}", trash_info.unwrap().color_content(true));
+    /// Reads both spec compliant .trashinfo files and the legacy format written by earlier versions
+    pub fn from_file(trash_info_path: &Path) -> TrashInfoResult<TrashInfo> {
+        let file = std::fs::read_to_string(trash_info_path)?;
+        let trash_file_name = trash_info_path.file_stem().ok_or("missing file name")?;
+        let trash_file_path: PathBuf = [
+            trash_info_path
+                .parent()
+                .and_then(Path::parent)
+                .unwrap_or_else(|| Path::new("")),
+            Path::new("files"),
+            Path::new(trash_file_name),
+        ]
+        .iter()
+        .collect();
+
+        let mut trash_info = Self::parse(&file, &trash_file_path)?;
+        if trash_info.source_path.is_relative() {
+            let trash_dirs =
+                TrashDirPaths::containing(trash_info_path).ok_or("relative Path outside of a trash can")?;
+            let topdir = trash_dirs
+                .topdir
+                .or_else(|| trash_dirs.trash_dir.parent().map(Path::to_path_buf))
+                .ok_or("relative Path without a topdir")?;
+            trash_info.source_path = topdir.join(&trash_info.source_path);
         }
+        Ok(trash_info)
     }
 
-    pub fn from_file(source_path: &Path) -> Result<TrashInfo, Box<dyn std::error::Error + Send + Sync + 'static>> {
-        let file = std::fs::read_to_string(source_path)?;
-        let mut lines = file.lines();
-
-        lines
-            .next()
-            .ok_or("missing header")?
-            .trim_start_exact(TrashInfo::HEADER)?;
-        let trash_file_name: PathBuf = lines
-            .next()
-            .ok_or("missing trash_file_name")?
-            .trim_start_exact(TrashInfo::FILENAME)?
-            .into();
-        let source_path: PathBuf = lines
-            .next()
-            .ok_or("missing source_path")?
-            .trim_start_exact(TrashInfo::PATH)?
-            .into();
-        let is_dir: bool = match lines
-            .next()
-            .ok_or("missing is_dir")?
-            .trim_start_exact(TrashInfo::ISDIR)?
-        {
-            "true" => true,
-            "false" => false,
-            string => Err(format!("expected bool, found {}", string))?,
-        };
-        let deletion_date = DateTime::parse_from_rfc3339(
-            lines
-                .next()
-                .ok_or("missing deletion date")?
-                .trim_start_exact(TrashInfo::DELETIONDATE)?,
-        )
-        .map_err(|_| "Cannot parse date")?
-        .with_timezone(&chrono::Utc);
+    /// The trashed file name is taken from `trash_file_path`, which is also where the size and type are read from
+    pub fn parse(content: &str, trash_file_path: &Path) -> TrashInfoResult<TrashInfo> {
+        let entries = parse_trash_info_group(content)?;
+        let trash_file_name: PathBuf = trash_file_path.file_name().ok_or("missing file name")?.into();
+
+        let source_path = entries.get(TrashInfo::PATH).ok_or("missing Path")?;
+        let deletion_date = parse_deletion_date(entries.get(TrashInfo::DELETIONDATE).ok_or("missing DeletionDate")?)?;
 
-        let file_size = lines.next().unwrap().trim_start_exact(TrashInfo::FILESIZE)?.into();
+        // the legacy format stored these and did not encode the path
+        if entries.contains_key(TrashInfo::FILENAME) {
+            let is_dir = match *entries.get(TrashInfo::ISDIR).ok_or("missing IsDir")? {
+                "true" => true,
+                "false" => false,
+                string => Err(format!("expected bool, found {}", string))?,
+            };
+            let file_size = entries.get(TrashInfo::FILESIZE).ok_or("missing FileSize")?.to_string();
+
+            return Ok(TrashInfo {
+                trash_file_name,
+                source_path: PathBuf::from(source_path),
+                is_dir,
+                deletion_date,
+                file_size,
+            });
+        }
+
+        let (is_dir, file_size) = match std::fs::symlink_metadata(trash_file_path) {
+            Ok(m) => (m.is_dir(), m.len().file_size(options::CONVENTIONAL).unwrap()),
+            Err(_) => (false, "-".to_string()),
+        };
 
         Ok(TrashInfo {
             trash_file_name,
-            source_path,
+            source_path: decode_path(source_path),
             is_dir,
             deletion_date,
             file_size,
@@ -143,28 +175,114 @@ impl TrashInfo {
     }
 }
 
-trait Trimmer {
-    fn trim_start_exact<'a>(
-        &'a self,
-        prefix: &str,
-    ) -> Result<&'a str, Box<dyn std::error::Error + Send + Sync + 'static>>;
-}
+pub type TrashInfoResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync + 'static>>;
 
-impl Trimmer for str {
-    fn trim_start_exact<'a>(
-        &'a self,
-        prefix: &str,
-    ) -> Result<&'a str, Box<dyn std::error::Error + Send + Sync + 'static>> {
-        let (start, data) = self.split_once(prefix).ok_or(format!(
-            "Incorrect format. Expected prefix `{}` found `{self}`.",
-            prefix
-        ))?;
-        if !start.is_empty() {
-            Err(format!(
-                "Incorrect format. Expected prefix `{}` found `{}{}`",
-                prefix, start, prefix
-            ))?;
+/// Collects the keys of the `[Trash Info]` group in any order.
+/// Blank lines, comments, other groups and unknown keys are skipped. The first of a repeated key wins.
+fn parse_trash_info_group(content: &str) -> TrashInfoResult<HashMap<&str, &str>> {
+    let mut entries = HashMap::new();
+    let mut found_header = false;
+    let mut in_group = false;
+
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            in_group = line == TrashInfo::HEADER;
+            found_header |= in_group;
+            continue;
         }
-        Ok(data)
+        if !in_group {
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or(format!("Incorrect format. Expected `key=value` found `{}`", line))?;
+        entries.entry(key.trim_end()).or_insert_with(|| value.trim_start());
+    }
+
+    if !found_header {
+        Err(format!("missing {} header", TrashInfo::HEADER))?;
+    }
+    Ok(entries)
+}
+
+/// Percent-encodes a path for the `Path=` key
+pub fn encode_path(path: &Path) -> String {
+    percent_encode(path.as_os_str().as_bytes(), PATH_ENCODE_SET).to_string()
+}
+
+pub fn decode_path(encoded: &str) -> PathBuf {
+    let bytes: Vec<u8> = percent_decode_str(encoded).collect();
+    PathBuf::from(OsStr::from_bytes(&bytes))
+}
+
+/// Accepts the spec's local `YYYY-MM-DDThh:mm:ss` and the RFC 3339 dates of the legacy format
+fn parse_deletion_date(date: &str) -> TrashInfoResult<DateTime<Local>> {
+    if let Ok(naive) = NaiveDateTime::parse_from_str(date, TrashInfo::DATE_FORMAT) {
+        return Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .ok_or_else(|| format!("Nonexistent local date {}", date).into());
     }
+
+    Ok(DateTime::parse_from_rfc3339(date)
+        .map_err(|_| "Cannot parse date")?
+        .with_timezone(&Local))
+}
+
+#[test]
+fn path_encoding() {
+    let path = PathBuf::from("/tmp/a dir/ü%.txt");
+    let encoded = encode_path(&path);
+    assert_eq!("/tmp/a%20dir/%C3%BC%25.txt", encoded);
+    assert_eq!(path, decode_path(&encoded));
+}
+
+#[test]
+fn spec_content() {
+    let trash_info = TrashInfo {
+        trash_file_name: PathBuf::from("foo bar.txt"),
+        source_path: PathBuf::from("/home/user/foo bar.txt"),
+        is_dir: false,
+        deletion_date: Local.with_ymd_and_hms(2004, 8, 31, 22, 32, 8).unwrap(),
+        file_size: "0 B".to_string(),
+    };
+    assert_eq!(
+        "[Trash Info]\nPath=/home/user/foo%20bar.txt\nDeletionDate=2004-08-31T22:32:08\n",
+        trash_info.content(None)
+    );
+    assert_eq!(
+        "[Trash Info]\nPath=user/foo%20bar.txt\nDeletionDate=2004-08-31T22:32:08\n",
+        trash_info.content(Some(Path::new("/home")))
+    );
+}
+
+#[test]
+fn parse_foreign_trash_info() {
+    let content = "# written by some file manager\n\n[Other Group]\nPath=/not/this\n\n[Trash Info]\nDeletionDate = 2004-08-31T22:32:08\nX-Unknown=1\nPath=/home/user/foo%20bar.txt\n";
+    let trash_info = TrashInfo::parse(content, Path::new("/nonexistent/Trash/files/foo bar.txt")).unwrap();
+
+    assert_eq!(PathBuf::from("foo bar.txt"), trash_info.trash_file_name);
+    assert_eq!(PathBuf::from("/home/user/foo bar.txt"), trash_info.source_path);
+    assert_eq!(
+        Local.with_ymd_and_hms(2004, 8, 31, 22, 32, 8).unwrap(),
+        trash_info.deletion_date
+    );
+}
+
+#[test]
+fn parse_legacy_trash_info() {
+    let content = "[Trash Info]\nFileName=a b.txt.23f9089d-62b2-4102-80ae-de95ee4d66d0\nPath=/tmp/a b.txt\nIsDir=false\nDeletionDate=2022-06-12T01:01:09.012176+00:00\nFileSize=13.11 KB";
+    let trash_info = TrashInfo::parse(
+        content,
+        Path::new("/nonexistent/Trash/files/a b.txt.23f9089d-62b2-4102-80ae-de95ee4d66d0"),
+    )
+    .unwrap();
+
+    assert_eq!(PathBuf::from("/tmp/a b.txt"), trash_info.source_path);
+    assert_eq!("13.11 KB", trash_info.file_size);
+    assert!(TrashInfo::parse("Path=/tmp/a\nDeletionDate=2022-06-12T01:01:09", Path::new("a")).is_err());
 }
@@ -0,0 +1,243 @@
+use crate::{
+    config::absolute_path, directory_sizes::SizeCache, output, trash_dir_paths::TrashDirPaths,
+    trash_file_paths::AbsoluteTrashPaths, trash_info::TrashInfo, trash_names::TrashNames, GLOBAL,
+};
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemType {
+    File,
+    Dir,
+    Link,
+}
+
+impl FromStr for ItemType {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "file" => Ok(ItemType::File),
+            "dir" => Ok(ItemType::Dir),
+            "link" => Ok(ItemType::Link),
+            _ => Err("Valid types are `file` `dir` `link`"),
+        }
+    }
+}
+
+impl fmt::Display for ItemType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ItemType::File => "file",
+            ItemType::Dir => "dir",
+            ItemType::Link => "link",
+        };
+        f.pad(name)
+    }
+}
+
+/// A trashed file or directory with its parsed .trashinfo
+pub struct TrashItem {
+    pub trash_paths: AbsoluteTrashPaths,
+    pub trash_info: TrashInfo,
+}
+
+impl TrashItem {
+    /// Every item with a readable .trashinfo in the trash can
+    pub fn in_trash_dirs(trash_dirs: &TrashDirPaths) -> Vec<TrashItem> {
+        trash_info_paths(trash_dirs)
+            .filter_map(|trash_info_path| {
+                let trash_info = TrashInfo::from_file(&trash_info_path).ok()?;
+                let trash_names = TrashNames::from_trash_info_name(trash_info_path.file_name()?.into());
+                Some(TrashItem {
+                    trash_paths: AbsoluteTrashPaths::new(trash_dirs.clone(), trash_names),
+                    trash_info,
+                })
+            })
+            .collect()
+    }
+
+    /// The entries of the trash can whose .trashinfo can't be parsed
+    pub fn unreadable_in_trash_dirs(trash_dirs: &TrashDirPaths) -> Vec<AbsoluteTrashPaths> {
+        trash_info_paths(trash_dirs)
+            .filter(|trash_info_path| TrashInfo::from_file(trash_info_path).is_err())
+            .filter_map(|trash_info_path| {
+                let trash_names = TrashNames::from_trash_info_name(trash_info_path.file_name()?.into());
+                Some(AbsoluteTrashPaths::new(trash_dirs.clone(), trash_names))
+            })
+            .collect()
+    }
+
+    /// Every readable item in every trash can
+    pub fn all() -> Vec<TrashItem> {
+        TrashDirPaths::all().iter().flat_map(Self::in_trash_dirs).collect()
+    }
+
+    /// Every item in the trash can that was trashed from `source_path`
+    pub fn find_by_source_path(source_path: &Path, trash_dirs: &TrashDirPaths) -> Vec<TrashItem> {
+        if GLOBAL.verbose() {
+            output::info(format!(
+                "Checking trash info files for the path {}",
+                source_path.display()
+            ));
+        }
+
+        Self::in_trash_dirs(trash_dirs)
+            .into_iter()
+            .filter(|item| item.trash_info.source_path == source_path)
+            .collect()
+    }
+
+    /// The items a user named. A trash name or a path in Trash/files or Trash/info is tried first, then the original
+    /// path. Errs when the argument names different items both ways, e.g. a counter name like `n.txt` that is also
+    /// the original path of `n.2.txt`
+    pub fn named<'a>(items: &'a [TrashItem], user_path: &str) -> Result<Vec<&'a TrashItem>, String> {
+        let path = absolute_path(user_path);
+        let by_trash_name: Vec<&TrashItem> = items
+            .iter()
+            .filter(|item| {
+                item.trash_info.trash_file_name == Path::new(user_path)
+                    || item.trash_paths.trash_file_path == path
+                    || item.trash_paths.trash_info_path == path
+            })
+            .collect();
+        let by_source_path: Vec<&TrashItem> = items
+            .iter()
+            .filter(|item| item.trash_info.source_path == path)
+            .collect();
+
+        let same_items = by_trash_name.len() == by_source_path.len()
+            && by_trash_name
+                .iter()
+                .zip(&by_source_path)
+                .all(|(a, b)| a.trash_paths.trash_info_path == b.trash_paths.trash_info_path);
+        match (by_trash_name.is_empty(), by_source_path.is_empty()) {
+            (false, false) if !same_items => Err(format!(
+                "{:?} is the trash name of one item and the original path of another. \
+                 Name it by its path in Trash/files, or by ./{} for the original path",
+                user_path, user_path
+            )),
+            (false, _) => Ok(by_trash_name),
+            (true, _) => Ok(by_source_path),
+        }
+    }
+
+    /// The path of `subpath` inside the trashed item. `None` when it doesn't exist, or when reaching it would
+    /// leave the item through `..`, an absolute path or a symlink
+    pub fn path_inside(&self, subpath: &Path) -> Option<PathBuf> {
+        let mut path = self.trash_paths.trash_file_path.clone();
+        for component in subpath.components() {
+            match component {
+                Component::CurDir => continue,
+                Component::Normal(name) => {
+                    // symlink_metadata doesn't follow the link, so a linked directory isn't a directory here
+                    if !path.symlink_metadata().ok()?.is_dir() {
+                        return None;
+                    }
+                    path.push(name);
+                }
+                _ => return None,
+            }
+        }
+        path.symlink_metadata().ok().map(|_| path)
+    }
+
+    /// The type of the trashed file itself. `None` when it is missing
+    pub fn item_type(&self) -> Option<ItemType> {
+        let file_type = self.trash_paths.trash_file_path.symlink_metadata().ok()?.file_type();
+        Some(match file_type {
+            t if t.is_symlink() => ItemType::Link,
+            t if t.is_dir() => ItemType::Dir,
+            _ => ItemType::File,
+        })
+    }
+
+    /// The size in bytes, from the directorysizes cache for directories
+    pub fn size(&self, size_cache: &mut SizeCache) -> Option<u64> {
+        let trash_file_path = &self.trash_paths.trash_file_path;
+        match self.item_type()? {
+            ItemType::Dir => size_cache
+                .size_of(trash_file_path, &self.trash_paths.trash_info_path)
+                .ok(),
+            _ => trash_file_path.symlink_metadata().ok().map(|m| m.len()),
+        }
+    }
+}
+
+fn trash_info_paths(trash_dirs: &TrashDirPaths) -> impl Iterator<Item = PathBuf> {
+    let extension = Path::new("e.trashinfo").extension();
+
+    trash_dirs
+        .get_all_info_paths()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(move |path| path.is_file() && path.extension() == extension)
+}
+
+#[test]
+fn names_items_one_way() {
+    let cwd = std::env::current_dir().unwrap();
+    let item = |trash_name: &str, source_path: PathBuf| TrashItem {
+        trash_paths: AbsoluteTrashPaths {
+            trash_info_path: PathBuf::from(format!("/nonexistent/Trash/info/{}.trashinfo", trash_name)),
+            trash_file_path: PathBuf::from(format!("/nonexistent/Trash/files/{}", trash_name)),
+        },
+        trash_info: TrashInfo {
+            trash_file_name: PathBuf::from(trash_name),
+            source_path,
+            is_dir: false,
+            deletion_date: chrono::Local::now(),
+            file_size: "0 B".to_string(),
+        },
+    };
+    let names = |items: &[TrashItem], user_path: &str| -> Result<Vec<PathBuf>, String> {
+        let matches = TrashItem::named(items, user_path)?;
+        Ok(matches
+            .iter()
+            .map(|item| item.trash_info.trash_file_name.clone())
+            .collect())
+    };
+
+    // counter naming: `n.txt` from elsewhere, then `n.txt` from the working directory as `n.2.txt`
+    let items = [
+        item("n.txt", PathBuf::from("/elsewhere/n.txt")),
+        item("n.2.txt", cwd.join("n.txt")),
+    ];
+    assert!(names(&items, "n.txt").is_err());
+    assert_eq!(vec![PathBuf::from("n.2.txt")], names(&items, "./n.txt").unwrap());
+    assert_eq!(vec![PathBuf::from("n.2.txt")], names(&items, "n.2.txt").unwrap());
+    assert_eq!(
+        vec![PathBuf::from("n.txt")],
+        names(&items, "/nonexistent/Trash/files/n.txt").unwrap()
+    );
+    assert!(names(&items, "other.txt").unwrap().is_empty());
+
+    // one item named both ways
+    let items = [item("n.txt", cwd.join("n.txt"))];
+    assert_eq!(vec![PathBuf::from("n.txt")], names(&items, "n.txt").unwrap());
+}
+
+/// A trashed `d/` holding `sub/file` and `link -> <outside>`, with `victim` in the outside directory
+#[cfg(test)]
+pub fn trashed_dir_with_link() -> (PathBuf, TrashItem) {
+    let (root, trash_dirs) = crate::trash_dir_paths::temp_trash_can("Trash");
+    std::fs::create_dir_all(trash_dirs.trash_files_dir.join("d/sub")).unwrap();
+    std::fs::create_dir_all(root.join("outside")).unwrap();
+    std::fs::write(root.join("outside/victim"), "keep").unwrap();
+    std::fs::write(trash_dirs.trash_files_dir.join("d/sub/file"), "contents").unwrap();
+    std::os::unix::fs::symlink(root.join("outside"), trash_dirs.trash_files_dir.join("d/link")).unwrap();
+    std::fs::write(
+        trash_dirs.trash_info_dir.join("d.trashinfo"),
+        format!(
+            "[Trash Info]\nPath={}\nDeletionDate=2024-05-01T10:20:30\n",
+            root.join("home/d").display()
+        ),
+    )
+    .unwrap();
+
+    let item = TrashItem::in_trash_dirs(&trash_dirs).remove(0);
+    (root, item)
+}
@@ -1,6 +1,7 @@
 use std::{
-    ffi::OsString,
+    ffi::{OsStr, OsString},
     path::{Path, PathBuf},
+    str::FromStr,
 };
 use uuid::Uuid;
 
@@ -10,12 +11,33 @@ pub struct TrashNames {
     pub trash_info_name: PathBuf,
 }
 
-// #[derive(Debug)]
-// pub enum StripError {
-//     TooShort,
-//     InvalidUuid,
-//     InvalidDelimiter,
-// }
+#[derive(Debug)]
+pub enum StripError {
+    TooShort,
+    InvalidUuid,
+    InvalidDelimiter,
+}
+
+/// How a trashed file is named in Trash/files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Naming {
+    /// `foo.txt.23f9089d-62b2-4102-80ae-de95ee4d66d0`
+    Uuid,
+    /// `foo.txt`, then `foo.2.txt` on collision
+    Counter,
+}
+
+impl FromStr for Naming {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uuid" => Ok(Naming::Uuid),
+            "counter" => Ok(Naming::Counter),
+            _ => Err("Valid namings are `uuid` `counter`"),
+        }
+    }
+}
 
 impl TrashNames {
     pub fn from_base_name(file_basename: PathBuf) -> TrashNames {
@@ -25,6 +47,35 @@ impl TrashNames {
         Self::from_trash_file_name(PathBuf::from(trash_file_name))
     }
 
+    /// `foo.txt` for n = 1, otherwise `foo.n.txt`. Names without an extension become `foo.n`
+    pub fn from_base_name_with_counter(file_basename: &Path, n: usize) -> TrashNames {
+        let file_name = file_basename.file_name().expect("not empty");
+        if n == 1 {
+            return Self::from_trash_file_name(PathBuf::from(file_name));
+        }
+
+        let mut trash_file_name = match (file_basename.file_stem(), file_basename.extension()) {
+            (Some(stem), Some(_)) => stem.to_owned(),
+            _ => file_name.to_owned(),
+        };
+        trash_file_name.push(format!(".{}", n));
+        if let Some(extension) = file_basename.extension() {
+            trash_file_name.push(".");
+            trash_file_name.push(extension);
+        }
+        Self::from_trash_file_name(PathBuf::from(trash_file_name))
+    }
+
+    /// Endless names to try for a base name. Counter names come in order, uuid names are new each time
+    pub fn candidates(naming: Naming, file_basename: &Path) -> Box<dyn Iterator<Item = TrashNames> + '_> {
+        match naming {
+            Naming::Uuid => Box::new(std::iter::repeat_with(move || {
+                Self::from_base_name(file_basename.to_path_buf())
+            })),
+            Naming::Counter => Box::new((1..).map(move |n| Self::from_base_name_with_counter(file_basename, n))),
+        }
+    }
+
     pub fn from_trash_info_name(trash_info_name: PathBuf) -> TrashNames {
         TrashNames {
             trash_file_name: PathBuf::from(trash_info_name.file_stem().expect("not empty")),
@@ -47,28 +98,52 @@ impl TrashNames {
         PathBuf::from(trash_info_name)
     }
 
-    // pub fn strip_uuid(trash_file_name: PathBuf) -> Result<(PathBuf, Uuid), StripError> {
-    //     let trash_file_name = match trash_file_name.extension().ok_or(StripError::InvalidDelimiter)? == "trashinfo" {
-    //         true => trash_file_name.file_stem().expect("not empty"),
-    //         false => trash_file_name.file_name().expect("not empty"),
-    //     };
-    //
-    //     // check that it has at least a 36 char uuid and a `.` in the final spot
-    //     println!("{:?}", trash_file_name);
-    //     let len = trash_file_name.len();
-    //     if len <= 37 {
-    //         return Err(StripError::TooShort);
-    //     }
-    //
-    //     // TODO check that this doesn't affect 한글 or anything
-    //     let trash_file_name = trash_file_name.to_string_lossy();
-    //     let (mut file_basename, uuid) = trash_file_name.split_at(len - 36);
-    //
-    //     file_basename = file_basename.strip_suffix('.').ok_or(StripError::InvalidDelimiter)?;
-    //     let uuid = Uuid::parse_str(uuid).map_err(|_| StripError::InvalidUuid)?;
-    //
-    //     Ok((PathBuf::from(file_basename), uuid))
-    // }
+    /// Why a Trash/info entry can't describe a trashed file, if it can't.
+    /// It must be `<name>.trashinfo` where `<name>` could be an entry of Trash/files
+    pub fn info_name_problem(trash_info_name: &OsStr) -> Option<&'static str> {
+        let trash_info_name = Path::new(trash_info_name);
+        if trash_info_name.extension() != Some("trashinfo".as_ref()) {
+            return Some("not a .trashinfo file");
+        }
+        match trash_info_name.file_stem() {
+            Some(stem) if stem != "." && stem != ".." => None,
+            _ => Some("names no file in Trash/files"),
+        }
+    }
+
+    /// Why a Trash/files entry can't have an info file, if it can't
+    pub fn file_name_problem(trash_file_name: &OsStr) -> Option<&'static str> {
+        // NAME_MAX on Linux
+        match trash_file_name.len() + ".trashinfo".len() > 255 {
+            true => Some("too long for its .trashinfo name"),
+            false => None,
+        }
+    }
+
+    /// Splits `foo.txt.<uuid>` or `foo.txt.<uuid>.trashinfo` into `foo.txt` and the uuid
+    pub fn strip_uuid(trash_file_name: &Path) -> Result<(PathBuf, Uuid), StripError> {
+        let trash_file_name = match trash_file_name.extension() == Some("trashinfo".as_ref()) {
+            true => trash_file_name.file_stem(),
+            false => trash_file_name.file_name(),
+        }
+        .ok_or(StripError::TooShort)?
+        .to_string_lossy();
+
+        // check that it has at least a 36 char uuid and a `.` in the final spot
+        let len = trash_file_name.len();
+        if len <= 37 {
+            return Err(StripError::TooShort);
+        }
+        if !trash_file_name.is_char_boundary(len - 36) {
+            return Err(StripError::InvalidUuid);
+        }
+        let (file_basename, uuid) = trash_file_name.split_at(len - 36);
+
+        let file_basename = file_basename.strip_suffix('.').ok_or(StripError::InvalidDelimiter)?;
+        let uuid = Uuid::parse_str(uuid).map_err(|_| StripError::InvalidUuid)?;
+
+        Ok((PathBuf::from(file_basename), uuid))
+    }
 }
 
 // #[test]
@@ -102,6 +177,21 @@ fn from_trash_file() {
     );
 }
 
+#[test]
+fn from_base_with_counter() {
+    let name = |base: &str, n| TrashNames::from_base_name_with_counter(Path::new(base), n).trash_file_name;
+
+    assert_eq!(PathBuf::from("foo.txt"), name("~/dev/foo.txt", 1));
+    assert_eq!(PathBuf::from("foo.2.txt"), name("~/dev/foo.txt", 2));
+    assert_eq!(PathBuf::from("foo.tar.3.gz"), name("foo.tar.gz", 3));
+    assert_eq!(PathBuf::from("Makefile.2"), name("Makefile", 2));
+    assert_eq!(PathBuf::from(".bashrc.2"), name(".bashrc", 2));
+    assert_eq!(
+        PathBuf::from("foo.2.txt.trashinfo"),
+        TrashNames::from_base_name_with_counter(Path::new("foo.txt"), 2).trash_info_name
+    );
+}
+
 #[test]
 fn from_trash_info() {
     let trash_names = TrashNames::from_trash_info_name(PathBuf::from(
@@ -117,27 +207,42 @@ fn from_trash_info() {
     );
 }
 
-// #[test]
-// fn strip_uuid() {
-//     let abs = "~/dev/";
-//     let file = "foo";
-//     let ex1 = ".txt";
-//     let ex2 = ".bz";
-//     let uuid = ".23f9089d-62b2-4102-80ae-de95ee4d66d0";
-//     let suffix = ".trashinfo";
-
-//     let asserter = |path: String, ex2| {
-//         assert_eq!(
-//             TrashNames::strip_uuid(PathBuf::from(path)).unwrap().0,
-//             PathBuf::from(format!("foo.txt{}", ex2))
-//         )
-//     };
-//     asserter(format!("{}{}{}{}{}{}", abs, file, ex1, ex2, uuid, suffix), ".bz");
-//     asserter(format!("{}{}{}{}{}", abs, file, ex1, ex2, uuid), ".bz");
-//     asserter(format!("{}{}{}{}{}", file, ex1, ex2, uuid, suffix), ".bz");
-//     asserter(format!("{}{}{}{}", file, ex1, ex2, uuid), ".bz");
-//     asserter(format!("{}{}{}{}{}", abs, file, ex1, uuid, suffix), "");
-//     asserter(format!("{}{}{}{}", abs, file, ex1, uuid), "");
-//     asserter(format!("{}{}{}{}", file, ex1, uuid, suffix), "");
-//     asserter(format!("{}{}{}", file, ex1, uuid), "");
-// }
+#[test]
+fn name_problems() {
+    let info = |name: &str| TrashNames::info_name_problem(name.as_ref());
+    assert_eq!(None, info("foo.txt.trashinfo"));
+    assert_eq!(None, info(".bashrc.trashinfo"));
+    assert!(info("foo.txt").is_some());
+    assert!(info(".trashinfo").is_some());
+    assert!(info("..trashinfo").is_some());
+    assert!(info("...trashinfo").is_some());
+
+    assert_eq!(None, TrashNames::file_name_problem("a".repeat(245).as_ref()));
+    assert!(TrashNames::file_name_problem("a".repeat(246).as_ref()).is_some());
+}
+
+#[test]
+fn strip_uuid() {
+    let abs = "~/dev/";
+    let file = "foo";
+    let ex1 = ".txt";
+    let ex2 = ".bz";
+    let uuid = ".23f9089d-62b2-4102-80ae-de95ee4d66d0";
+    let suffix = ".trashinfo";
+
+    let asserter = |path: String, ex2| {
+        assert_eq!(
+            TrashNames::strip_uuid(&PathBuf::from(path)).unwrap().0,
+            PathBuf::from(format!("foo.txt{}", ex2))
+        )
+    };
+    asserter(format!("{}{}{}{}{}{}", abs, file, ex1, ex2, uuid, suffix), ".bz");
+    asserter(format!("{}{}{}{}{}", abs, file, ex1, ex2, uuid), ".bz");
+    asserter(format!("{}{}{}{}{}", file, ex1, ex2, uuid, suffix), ".bz");
+    asserter(format!("{}{}{}{}", file, ex1, ex2, uuid), ".bz");
+    asserter(format!("{}{}{}{}{}", abs, file, ex1, uuid, suffix), "");
+    asserter(format!("{}{}{}{}", abs, file, ex1, uuid), "");
+    asserter(format!("{}{}{}{}", file, ex1, uuid, suffix), "");
+    asserter(format!("{}{}{}", file, ex1, uuid), "");
+    assert!(TrashNames::strip_uuid(Path::new("foo.2.txt")).is_err());
+}
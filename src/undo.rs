@@ -0,0 +1,453 @@
+use crate::{
+    config::Config,
+    directory_sizes::DirectorySizes,
+    file_ops,
+    journal::{Item, Journal, Operation, OperationKind},
+    list::print_ignoring_closed_pipe,
+    output,
+    trash::try_put,
+    trash_dir_paths::TrashDirPaths,
+    trash_file_paths::{AbsoluteTrashPaths, AbsoluteTrashPathsError},
+    trash_names::{Naming, TrashNames},
+    GLOBAL,
+};
+use std::{
+    collections::HashSet,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+/// Reverses an operation from the journal, the most recent one with items left to undo when no id is given.
+/// Items that can't be reversed are reported and the rest still are. Running it again retries them
+pub fn undo(id: Option<&str>, naming: Naming) {
+    let journal = Journal::home();
+    let operations = journal.operations();
+    let operation = match id {
+        Some(id) => match operations.iter().find(|operation| operation.id == id) {
+            Some(operation) => operation,
+            None => {
+                output::error(format!("No operation {} in the journal", id));
+                output::hint("See the journal with trash undo --list");
+                output::exit(1)
+            }
+        },
+        None => match latest_pending(&operations) {
+            Some(operation) => operation,
+            None => {
+                output::info("Nothing to undo");
+                return;
+            }
+        },
+    };
+
+    match operation.kind() {
+        OperationKind::Purge => {
+            output::error(format!(
+                "Operation {} permanently deleted its items, it can't be undone",
+                operation.id
+            ));
+            output::exit(1)
+        }
+        OperationKind::Undo(_) => {
+            output::error(format!("Operation {} is an undo, it can't be undone", operation.id));
+            output::exit(1)
+        }
+        OperationKind::Put | OperationKind::Restore | OperationKind::CreateDir => {}
+    }
+
+    let pending = pending_items(&operations, operation);
+    if pending.is_empty() {
+        output::info(format!("Operation {} is already undone", operation.id));
+        return;
+    }
+
+    let undone = undo_items(&journal, operation, &pending, naming);
+    output::info(format!(
+        "Undid {} of {} items of {} {}",
+        undone,
+        pending.len(),
+        operation.kind(),
+        operation.id
+    ));
+    if undone < pending.len() {
+        output::hint(format!(
+            "Fix the problems above and run trash undo {} to retry the rest",
+            operation.id
+        ));
+        output::exit(1);
+    }
+}
+
+/// Reverses `pending` items of `operation`, recording each one that was. Returns how many were.
+/// Restored items that go back to the trash as items of their own are named with `naming`
+fn undo_items(journal: &Journal, operation: &Operation, pending: &[&Item], naming: Naming) -> usize {
+    let mut undone = 0;
+    // last in, first out: a restore that backed up a file gives the file back after re-trashing the item
+    for item in pending.iter().rev() {
+        let result = match item.kind {
+            OperationKind::Put => untrash(&item.trash_file_path, &item.path),
+            OperationKind::Restore => retrash(&item.trash_file_path, &item.path, naming),
+            OperationKind::CreateDir => remove_created_dir(&item.path),
+            _ => Err(format!("{} can't be undone", item.path.display())),
+        };
+        match result {
+            Ok(()) => {
+                journal.record(
+                    OperationKind::Undo(operation.id.clone()),
+                    &item.trash_file_path,
+                    &item.path,
+                );
+                undone += 1;
+            }
+            Err(e) => output::warning(e),
+        }
+    }
+    undone
+}
+
+/// Prints the journal, newest first
+pub fn print_journal() {
+    let operations = Journal::home().operations();
+    let mut output = String::new();
+    for operation in operations.iter().rev() {
+        let done = undone_items(&operations, &operation.id).len();
+        let status = match done {
+            0 => "",
+            n if n < operation.items.len() => "partly undone",
+            _ => "undone",
+        };
+        output.push_str(&format!(
+            "{}  {}  {:<16}  {:>5} items  {:<13}  {}\n",
+            operation.id,
+            operation.time.format("%Y-%m-%d %H:%M:%S"),
+            operation.kind(),
+            operation.items.len(),
+            status,
+            operation.cwd.display()
+        ));
+    }
+    print_ignoring_closed_pipe(&output);
+}
+
+fn is_undoable(operation: &Operation) -> bool {
+    matches!(operation.kind(), OperationKind::Put | OperationKind::Restore)
+}
+
+/// The newest put or restore with items that weren't undone yet, e.g. after a partial undo
+fn latest_pending(operations: &[Operation]) -> Option<&Operation> {
+    operations
+        .iter()
+        .rev()
+        .find(|operation| is_undoable(operation) && !pending_items(operations, operation).is_empty())
+}
+
+/// The items of `operation` that weren't undone yet, in the order they were recorded
+fn pending_items<'a>(operations: &[Operation], operation: &'a Operation) -> Vec<&'a Item> {
+    let done = undone_items(operations, &operation.id);
+    operation
+        .items
+        .iter()
+        .filter(|item| !done.contains(&(item.trash_file_path.clone(), item.path.clone())))
+        .collect()
+}
+
+/// Trash file paths and paths of the items of operation `id` that were undone
+fn undone_items(operations: &[Operation], id: &str) -> HashSet<(PathBuf, PathBuf)> {
+    operations
+        .iter()
+        .flat_map(|operation| &operation.items)
+        .filter(|item| item.kind == OperationKind::Undo(id.to_string()))
+        .map(|item| (item.trash_file_path.clone(), item.path.clone()))
+        .collect()
+}
+
+/// Moves a trashed item back to where it was trashed from, never overwriting
+fn untrash(trash_file_path: &Path, path: &Path) -> Result<(), String> {
+    let (Some(trash_dirs), Some(name)) = (TrashDirPaths::containing(trash_file_path), trash_file_path.file_name())
+    else {
+        return Err(format!("{} is not in a trash can", trash_file_path.display()));
+    };
+    let trash_paths = AbsoluteTrashPaths::new(trash_dirs, TrashNames::from_trash_file_name(PathBuf::from(name)));
+    if trash_paths.trash_file_path.symlink_metadata().is_err() {
+        return Err(format!("{} is no longer in the trash", path.display()));
+    }
+    if path.symlink_metadata().is_ok() {
+        return Err(format!("{} already exists", path.display()));
+    }
+
+    if let Some(parent) = path.parent().filter(|parent| !parent.is_dir()) {
+        if GLOBAL.verbose() {
+            output::info(format!("Creating {}", parent.display()));
+        }
+        std::fs::create_dir_all(parent).map_err(|e| format!("Unable to create {}. {}", parent.display(), e))?;
+    }
+    trash_paths.try_restore_from_trash(path).map_err(|e| e.to_string())?;
+    if let Err(e) = trash_paths.try_delete_info_file() {
+        output::warning(e);
+    }
+    DirectorySizes::forget(&trash_paths.trash_file_path);
+    Ok(())
+}
+
+/// Moves a restored item back to the trash can it was restored from, or to the usual trash can for `path`
+/// when it is now on another filesystem. A file restored from inside a trashed directory goes back in it
+/// while the directory is still trashed
+fn retrash(trash_file_path: &Path, path: &Path, naming: Naming) -> Result<(), String> {
+    let Some(file_basename) = path.file_name() else {
+        return Err(format!("{} has no file name", path.display()));
+    };
+    let Ok(metadata) = path.symlink_metadata() else {
+        return Err(format!("{} no longer exists", path.display()));
+    };
+    if let Some(trash_dirs) = trash_can_of(trash_file_path) {
+        let parent = trash_file_path
+            .parent()
+            .filter(|parent| *parent != trash_dirs.trash_files_dir);
+        if parent.is_some_and(|parent| parent.symlink_metadata().is_ok_and(|m| m.is_dir())) {
+            return put_back_inside(&trash_dirs, trash_file_path, path);
+        }
+    }
+
+    let config = Config {
+        source_path: path.to_path_buf(),
+        file_basename: PathBuf::from(file_basename),
+    };
+    let trash_dirs = trash_can_of(trash_file_path)
+        .filter(|trash_dirs| {
+            std::fs::metadata(&trash_dirs.trash_dir).is_ok_and(|trash_metadata| trash_metadata.dev() == metadata.dev())
+        })
+        .unwrap_or_else(|| TrashDirPaths::for_path(path));
+    try_put(&config, naming, &trash_dirs).map(|_| ()).map_err(|e| {
+        if let AbsoluteTrashPathsError::TrashFile(_, trash_paths) = &e {
+            let _ = trash_paths.try_delete_info_file();
+        }
+        format!("{} {}", path.display(), e)
+    })
+}
+
+/// Moves a file restored from inside a trashed directory back to where it was in the directory
+fn put_back_inside(trash_dirs: &TrashDirPaths, trash_file_path: &Path, path: &Path) -> Result<(), String> {
+    if trash_file_path.symlink_metadata().is_ok() {
+        return Err(format!("{} already exists", trash_file_path.display()));
+    }
+    if GLOBAL.verbose() {
+        output::info(format!(
+            "Moving {} back to {}",
+            path.display(),
+            trash_file_path.display()
+        ));
+    }
+    file_ops::move_path(path, trash_file_path).map_err(|e| format!("{} {}", path.display(), e))?;
+
+    // the trashed directory got bigger
+    let item_path = trash_file_path
+        .ancestors()
+        .find(|ancestor| ancestor.parent() == Some(trash_dirs.trash_files_dir.as_path()));
+    if let Some(item_path) = item_path {
+        let trash_paths = AbsoluteTrashPaths::new(
+            trash_dirs.clone(),
+            TrashNames::from_trash_file_name(item_path.to_path_buf()),
+        );
+        DirectorySizes::record(&trash_paths.trash_file_path, &trash_paths.trash_info_path);
+    }
+    Ok(())
+}
+
+/// Removes a directory a restore created. Fails while something else is in it, the undo can be retried after
+fn remove_created_dir(dir: &Path) -> Result<(), String> {
+    if dir.symlink_metadata().is_err() {
+        return Ok(());
+    }
+    std::fs::remove_dir(dir).map_err(|e| format!("Unable to remove {}. {}", dir.display(), e))
+}
+
+/// The existing trash can of a path in Trash/files, which may be inside a trashed directory.
+/// The outermost wins, a trashed home directory can hold a trash can of its own
+fn trash_can_of(trash_file_path: &Path) -> Option<TrashDirPaths> {
+    trash_file_path
+        .ancestors()
+        .filter(|path| path.parent().and_then(Path::file_name) == Some("files".as_ref()))
+        .filter_map(TrashDirPaths::containing)
+        .filter(|trash_dirs| trash_dirs.trash_info_dir.is_dir())
+        .last()
+}
+
+/// Trashes a new file at `path` as part of `journal`'s operation
+#[cfg(test)]
+fn put_recorded(journal: &Journal, trash_dirs: &TrashDirPaths, path: &Path) -> AbsoluteTrashPaths {
+    std::fs::write(path, "contents").unwrap();
+    let config = Config {
+        source_path: path.to_path_buf(),
+        file_basename: PathBuf::from(path.file_name().unwrap()),
+    };
+    let trash_paths = try_put(&config, Naming::Uuid, trash_dirs).ok().unwrap();
+    journal.record(OperationKind::Put, &trash_paths.trash_file_path, path);
+    trash_paths
+}
+
+#[test]
+fn undoes_put() {
+    let (root, trash_dirs) = crate::trash_dir_paths::temp_trash_can("Trash");
+    let journal = Journal::in_trash_dir(&trash_dirs.trash_dir, "put00001");
+    let path = root.join("a.txt");
+    let trash_paths = put_recorded(&journal, &trash_dirs, &path);
+
+    let operations = journal.operations();
+    let operation = latest_pending(&operations).unwrap();
+    assert_eq!("put00001", operation.id);
+    let undo_journal = Journal::in_trash_dir(&trash_dirs.trash_dir, "undo0001");
+    assert_eq!(
+        1,
+        undo_items(
+            &undo_journal,
+            operation,
+            &pending_items(&operations, operation),
+            Naming::Uuid
+        )
+    );
+
+    assert_eq!("contents", std::fs::read_to_string(&path).unwrap());
+    assert!(trash_paths.trash_info_path.symlink_metadata().is_err());
+    assert!(latest_pending(&journal.operations()).is_none());
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn undoes_restore() {
+    let (root, trash_dirs) = crate::trash_dir_paths::temp_trash_can("Trash");
+    let path = root.join("a.txt");
+    let trash_paths = put_recorded(
+        &Journal::in_trash_dir(&trash_dirs.trash_dir, "put00001"),
+        &trash_dirs,
+        &path,
+    );
+    trash_paths.try_restore_from_trash(&path).ok().unwrap();
+    trash_paths.try_delete_info_file().ok().unwrap();
+    let journal = Journal::in_trash_dir(&trash_dirs.trash_dir, "rest0001");
+    journal.record(OperationKind::Restore, &trash_paths.trash_file_path, &path);
+
+    let operations = journal.operations();
+    let operation = latest_pending(&operations).unwrap();
+    assert_eq!("rest0001", operation.id);
+    let undo_journal = Journal::in_trash_dir(&trash_dirs.trash_dir, "undo0001");
+    assert_eq!(
+        1,
+        undo_items(
+            &undo_journal,
+            operation,
+            &pending_items(&operations, operation),
+            Naming::Uuid
+        )
+    );
+
+    // back in the same trash can, under a new name
+    assert!(path.symlink_metadata().is_err());
+    assert_eq!(1, std::fs::read_dir(&trash_dirs.trash_files_dir).unwrap().count());
+    assert_eq!(1, std::fs::read_dir(&trash_dirs.trash_info_dir).unwrap().count());
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn undoes_restore_of_dangling_symlink() {
+    let (root, trash_dirs) = crate::trash_dir_paths::temp_trash_can("Trash");
+    let path = root.join("link");
+    std::os::unix::fs::symlink(root.join("missing"), &path).unwrap();
+    let config = Config {
+        source_path: path.clone(),
+        file_basename: PathBuf::from("link"),
+    };
+    let trash_paths = try_put(&config, Naming::Uuid, &trash_dirs).ok().unwrap();
+    trash_paths.try_restore_from_trash(&path).ok().unwrap();
+    trash_paths.try_delete_info_file().ok().unwrap();
+    let journal = Journal::in_trash_dir(&trash_dirs.trash_dir, "rest0001");
+    journal.record(OperationKind::Restore, &trash_paths.trash_file_path, &path);
+
+    let operations = journal.operations();
+    let operation = latest_pending(&operations).unwrap();
+    let undo_journal = Journal::in_trash_dir(&trash_dirs.trash_dir, "undo0001");
+    assert_eq!(
+        1,
+        undo_items(
+            &undo_journal,
+            operation,
+            &pending_items(&operations, operation),
+            Naming::Uuid
+        )
+    );
+
+    assert!(path.symlink_metadata().is_err());
+    let retrashed = std::fs::read_dir(&trash_dirs.trash_files_dir)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_eq!(root.join("missing"), std::fs::read_link(retrashed.path()).unwrap());
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn undoes_subpath_restore() {
+    let (root, item) = crate::trash_item::trashed_dir_with_link();
+    let trash_file_path = item.path_inside(Path::new("sub/file")).unwrap();
+    let destination = item.trash_info.source_path.join("sub/file");
+    let journal = Journal::in_trash_dir(&root.join("Trash"), "rest0001");
+    crate::trash::move_out_of_trash(&journal, &trash_file_path, &destination).unwrap();
+    assert!(destination.is_file());
+
+    let operations = journal.operations();
+    let operation = latest_pending(&operations).unwrap();
+    let undo_journal = Journal::in_trash_dir(&root.join("Trash"), "undo0001");
+    assert_eq!(
+        4,
+        undo_items(
+            &undo_journal,
+            operation,
+            &pending_items(&operations, operation),
+            Naming::Uuid
+        )
+    );
+
+    // back inside the trashed directory, without the directories the restore created
+    assert_eq!("contents", std::fs::read_to_string(&trash_file_path).unwrap());
+    assert!(root.join("home").symlink_metadata().is_err());
+    assert_eq!(1, std::fs::read_dir(root.join("Trash/files")).unwrap().count());
+    assert!(latest_pending(&journal.operations()).is_none());
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn retries_partial_undo() {
+    let (root, trash_dirs) = crate::trash_dir_paths::temp_trash_can("Trash");
+    let journal = Journal::in_trash_dir(&trash_dirs.trash_dir, "put00001");
+    put_recorded(&journal, &trash_dirs, &root.join("a.txt"));
+    put_recorded(&journal, &trash_dirs, &root.join("b.txt"));
+    // b.txt can't be untrashed while something else is in its place
+    std::fs::write(root.join("b.txt"), "in the way").unwrap();
+
+    let undo = |id: &str| {
+        let operations = journal.operations();
+        let operation = latest_pending(&operations).expect("items left");
+        assert_eq!("put00001", operation.id);
+        let pending = pending_items(&operations, operation);
+        let undone = undo_items(
+            &Journal::in_trash_dir(&trash_dirs.trash_dir, id),
+            operation,
+            &pending,
+            Naming::Uuid,
+        );
+        (undone, pending.len())
+    };
+    assert_eq!((1, 2), undo("undo0001"));
+    assert_eq!("contents", std::fs::read_to_string(root.join("a.txt")).unwrap());
+
+    std::fs::remove_file(root.join("b.txt")).unwrap();
+    assert_eq!((1, 1), undo("undo0002"));
+    assert_eq!("contents", std::fs::read_to_string(root.join("b.txt")).unwrap());
+    assert!(latest_pending(&journal.operations()).is_none());
+
+    std::fs::remove_dir_all(root).unwrap();
+}